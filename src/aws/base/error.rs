@@ -0,0 +1,30 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// The error for a line of the AWS file that can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub column: usize,
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: \"{}\"",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message,
+            self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,53 +1,281 @@
-use super::Profile;
+use super::{header, profile::invalid_line, ParseError, Profile, SectionKind};
+use crate::diff;
+use crate::fs::write_atomic;
 use crate::Result;
 
 use anyhow::anyhow;
-use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::path::Path;
 
+/// Which AWS file is loaded. They have different rules for the section headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Config,
+    Credentials,
+}
+
+/// Where a newly generated profile is put in the file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// Right after the profile it was generated from.
+    #[default]
+    AfterSource,
+    /// At the end of the file.
+    End,
+}
+
 pub struct ConfFile {
     formatter: Box<dyn Fn(&str) -> String>,
+    /// Lines before the first section, such as leading comments.
+    preamble: Vec<String>,
     profiles: Vec<Profile>,
+    trailing_newline: bool,
+    line_ending: LineEnding,
+    bom: bool,
+}
+
+/// The line ending of the file, which is kept on writing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn detect(content: &str) -> Self {
+        match content.find('\n') {
+            Some(i) if content[..i].ends_with('\r') => Self::CrLf,
+            _ => Self::Lf,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
 }
 
 impl ConfFile {
     pub fn profile(&self, name: &str) -> Option<&Profile> {
-        self.profiles.iter().find(|p| p.name() == name)
+        self.section(SectionKind::Profile, name)
+    }
+
+    /// Find the section of the kind. Both `Default` and `Profile` find a profile section.
+    pub fn section(&self, kind: SectionKind, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| same_section(p, kind, name))
+    }
+
+    /// All sections in the order of the file.
+    pub fn sections(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// The sections which appear more than once. The same section can be written twice
+    /// by `[default]` and `[profile default]`.
+    pub fn duplicates(&self) -> Vec<&Profile> {
+        self.profiles
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| {
+                self.profiles[..*i]
+                    .iter()
+                    .any(|other| same_section(other, p.kind(), p.name()))
+            })
+            .map(|(_, p)| p)
+            .collect()
     }
 
+    /// Set the profile. An existing profile with the same name is replaced in place and
+    /// a new one is appended to the end of the file.
     pub fn set(self, profile: Profile) -> Self {
-        let name = profile.name();
-        let mut profiles = self
-            .profiles
-            .into_iter()
-            .filter(|p| p.name() != name)
-            .collect::<Vec<Profile>>();
+        let index = self.profiles.len();
+        self.set_at(profile, index)
+    }
 
-        profiles.push(profile);
+    /// Set the value of the key in the profile. The profile is created if it doesn't exist.
+    pub fn set_value(self, name: &str, key: &str, value: &str) -> Self {
+        let profile = self
+            .profile(name)
+            .cloned()
+            .unwrap_or_else(|| Profile::new(name))
+            .set(key, value);
+        self.set(profile)
+    }
+
+    /// Remove the key from the profile if the profile exists.
+    pub fn remove_value(self, name: &str, key: &str) -> Self {
+        match self.profile(name).cloned() {
+            Some(profile) => self.set(profile.remove(key)),
+            None => self,
+        }
+    }
 
-        Self { profiles, ..self }
+    /// Set the profile. An existing profile with the same name is replaced in place and
+    /// a new one is inserted right after the anchor profile, or appended to the end of
+    /// the file if the anchor doesn't exist.
+    pub fn set_after(self, profile: Profile, anchor: &str) -> Self {
+        let index = self
+            .position(anchor)
+            .map(|i| i + 1)
+            .unwrap_or(self.profiles.len());
+        self.set_at(profile, index)
+    }
+
+    fn set_at(self, profile: Profile, index: usize) -> Self {
+        let mut preamble = self.preamble;
+        let mut profiles = self.profiles;
+
+        if let Some(i) = profiles
+            .iter()
+            .position(|p| same_section(p, profile.kind(), profile.name()))
+        {
+            profiles[i] = profiles[i].replace_with(profile);
+
+            return Self {
+                preamble,
+                profiles,
+                ..self
+            };
+        }
+
+        // The trailer of the preceding profile stays above the section it belongs to.
+        let mut trailer = vec![];
+
+        // Separate the new profile from the preceding content by a blank line.
+        if index > 0 {
+            let (prev, lines) = profiles[index - 1].clone().split_trailer();
+            profiles[index - 1] = prev.push("");
+            trailer = lines;
+        } else if preamble
+            .last()
+            .map(|l| !l.trim().is_empty())
+            .unwrap_or(false)
+        {
+            preamble.push("".into());
+        }
+
+        // And from the following content as well.
+        let (mut profile, _) = profile.split_trailer();
+        if index < profiles.len() || trailer.iter().any(|l| !l.trim().is_empty()) {
+            if trailer.first().is_none_or(|l| !l.trim().is_empty()) {
+                profile = profile.push("");
+            }
+            for line in trailer.iter() {
+                profile = profile.push(line);
+            }
+        }
+
+        profiles.insert(index, profile);
+
+        Self {
+            preamble,
+            profiles,
+            ..self
+        }
+    }
+
+    /// Remove the profile, including its duplicates. The comments above the next section
+    /// are kept with it, and the blank line which separated the profile from the preceding
+    /// one is removed when it was at the end of the file.
+    pub fn remove(self, name: &str) -> Self {
+        let mut preamble = self.preamble;
+        let mut profiles: Vec<Profile> = vec![];
+        let mut removed_last = false;
+
+        for profile in self.profiles {
+            removed_last = same_section(&profile, SectionKind::Profile, name);
+            if !removed_last {
+                profiles.push(profile);
+                continue;
+            }
+
+            let (_, trailer) = profile.split_trailer();
+            let comments = trailer
+                .into_iter()
+                .skip_while(|l| l.trim().is_empty())
+                .collect::<Vec<String>>();
+
+            if comments.is_empty() {
+                continue;
+            }
+
+            match profiles.pop() {
+                Some(prev) => {
+                    let prev = comments
+                        .iter()
+                        .fold(prev.trim_end().push(""), |p, l| p.push(l));
+                    profiles.push(prev);
+                }
+                None => {
+                    trim_blank_lines(&mut preamble);
+                    if !preamble.is_empty() {
+                        preamble.push("".into());
+                    }
+                    preamble.extend(comments);
+                }
+            }
+        }
+
+        if removed_last {
+            match profiles.pop() {
+                Some(prev) => profiles.push(prev.trim_end()),
+                None => trim_blank_lines(&mut preamble),
+            }
+        }
+
+        Self {
+            preamble,
+            profiles,
+            ..self
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|p| same_section(p, SectionKind::Profile, name))
     }
 
     pub fn write(&self, path: &Path) -> Result<()> {
-        std::fs::write(path, self.format()).map_err(|err| {
-            anyhow!(
-                "Error writing to \"{}\". {}",
-                path.to_str().unwrap_or("unknown path"),
-                err
-            )
+        write_atomic(path, self.format().as_bytes())
+    }
+
+    /// The unified diff between the file at the path and this content, with the values
+    /// of the secret keys redacted.
+    pub fn diff(&self, path: &Path, secret_keys: &[&str]) -> Result<String> {
+        diff::unified(path, &self.format(), |line| {
+            diff::redact_ini(line, secret_keys)
         })
     }
 
-    fn format(&self) -> String {
+    pub fn format(&self) -> String {
         let fmt = &self.formatter;
-        self.profiles
+        let newline = self.line_ending.as_str();
+        let mut content = self
+            .preamble
             .iter()
-            .map(|p| p.format(fmt))
+            .cloned()
+            .chain(self.profiles.iter().map(|p| p.format(fmt)))
             .collect::<Vec<String>>()
-            .join("\n\n")
+            .join("\n");
+
+        if self.trailing_newline && !content.is_empty() {
+            content.push('\n');
+        }
+
+        if newline != "\n" {
+            content = content.replace('\n', newline);
+        }
+
+        if self.bom {
+            content.insert(0, BOM);
+        }
+
+        content
     }
 }
 
@@ -65,20 +293,25 @@ impl PartialEq for ConfFile {
             return false;
         }
 
-        self.profiles.iter().all(|p| match other.profile(p.name()) {
-            Some(other_p) => *p == *other_p,
-            None => false,
-        })
+        self.profiles
+            .iter()
+            .all(|p| match other.section(p.kind(), p.name()) {
+                Some(other_p) => *p == *other_p,
+                None => false,
+            })
     }
 }
 
 type Formatter = Box<dyn Fn(&str) -> String>;
 
+const BOM: char = '\u{feff}';
+
 #[derive(Default)]
 pub struct ConfLoader<'a> {
-    reg_profile: Option<&'a str>,
+    file_type: Option<FileType>,
     path: Option<&'a Path>,
     formatter: Option<Formatter>,
+    strict: bool,
 }
 
 impl<'a> ConfLoader<'a> {
@@ -86,9 +319,9 @@ impl<'a> ConfLoader<'a> {
         Self::default()
     }
 
-    pub fn set_reg_profile(self, reg_profile: &'a str) -> Self {
+    pub fn set_file_type(self, file_type: FileType) -> Self {
         Self {
-            reg_profile: Some(reg_profile),
+            file_type: Some(file_type),
             ..self
         }
     }
@@ -107,10 +340,16 @@ impl<'a> ConfLoader<'a> {
         }
     }
 
+    /// In strict mode, the loader fails on the lines it doesn't recognize instead of
+    /// keeping them as they are.
+    pub fn set_strict(self, strict: bool) -> Self {
+        Self { strict, ..self }
+    }
+
     pub fn load(self) -> Result<ConfFile> {
-        let reg_profile = match self.reg_profile {
-            Some(r) => r,
-            None => return Err(anyhow!("reg_profile is not set")),
+        let file_type = match self.file_type {
+            Some(t) => t,
+            None => return Err(anyhow!("file_type is not set")),
         };
 
         let path = match self.path {
@@ -123,47 +362,99 @@ impl<'a> ConfLoader<'a> {
             None => return Err(anyhow!("formatter is not set")),
         };
 
-        let reader = BufReader::new(File::open(path)?);
+        // A missing file is an empty document, which is created on writing.
+        let content = if path.exists() {
+            std::fs::read_to_string(path)
+                .map_err(|err| anyhow!("Error reading \"{}\". {}", path.display(), err))?
+        } else {
+            String::new()
+        };
+        let (bom, content) = match content.strip_prefix(BOM) {
+            Some(content) => (true, content),
+            None => (false, content.as_str()),
+        };
+        let mut preamble: Vec<String> = vec![];
         let mut profiles: Vec<Profile> = vec![];
-        let mut profile = Profile::new("");
+        let mut profile: Option<Profile> = None;
 
-        for line in reader.lines() {
-            let line = line?;
+        for (index, line) in content.lines().enumerate() {
+            // A stray carriage return must not end up in the values.
+            let line = line.strip_suffix('\r').unwrap_or(line);
+
+            if self.strict {
+                check_line(path, file_type, index, line, profile.is_none())?;
+            }
 
-            if let Some(name) = capture(reg_profile, &line) {
-                if !profile.name().is_empty() {
-                    profiles.push(profile.clone());
+            if let Some((kind, name)) = header::parse(line, file_type) {
+                if let Some(p) = profile.take() {
+                    profiles.push(p);
                 }
 
-                profile = Profile::new(name);
-            } else if !line.is_empty() {
-                profile = profile.push(&line);
+                profile = Some(Profile::with_kind(kind, &name).set_header(line));
+            } else {
+                match profile {
+                    Some(p) => profile = Some(p.push(line)),
+                    None => preamble.push(line.into()),
+                }
             }
         }
 
-        if !profile.name().is_empty() {
-            profiles.push(profile);
+        if let Some(p) = profile {
+            profiles.push(p);
         }
 
         Ok(ConfFile {
             formatter,
+            preamble,
             profiles,
+            trailing_newline: content.is_empty() || content.ends_with('\n'),
+            line_ending: LineEnding::detect(content),
+            bom,
         })
     }
 }
 
-fn capture<'a>(pattern: &'a str, line: &'a str) -> Option<&'a str> {
-    let line = line.trim();
+fn check_line(
+    path: &Path,
+    file_type: FileType,
+    index: usize,
+    line: &str,
+    in_preamble: bool,
+) -> Result<()> {
+    let message = match invalid_line(line) {
+        Some(_) if header::parse(line, file_type).is_some() => None,
+        Some(message) => Some(message),
+        None if in_preamble && line.contains('=') => Some("key-value pair outside of any section"),
+        None => None,
+    };
+
+    match message {
+        Some(message) => Err(ParseError {
+            path: path.into(),
+            line: index + 1,
+            column: line.len() - line.trim_start().len() + 1,
+            text: line.trim().into(),
+            message: message.into(),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
 
-    if line == "[default]" {
-        return Some("default");
+fn trim_blank_lines(lines: &mut Vec<String>) {
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
     }
+}
 
-    Regex::new(pattern)
-        .unwrap()
-        .captures(line)
-        .and_then(|caps| caps.get(1))
-        .map(|mat| mat.as_str())
+fn same_section(p: &Profile, kind: SectionKind, name: &str) -> bool {
+    let same_kind = if kind.is_profile() {
+        p.kind().is_profile()
+    } else {
+        p.kind() == kind
+    };
+
+    same_kind && p.name() == name
 }
 
 #[cfg(test)]
@@ -173,8 +464,6 @@ mod tests {
     mod conf_file {
         use super::*;
 
-        const REG_PROFILE: &str = r"^\[profile (.+)\]";
-
         fn build() -> ConfFile {
             let fmt = Box::new(|p: &str| {
                 if p == "default" {
@@ -186,7 +475,7 @@ mod tests {
 
             ConfLoader::new()
                 .set_path(Path::new("mock/test_base"))
-                .set_reg_profile(REG_PROFILE)
+                .set_file_type(FileType::Config)
                 .set_formatter(fmt)
                 .load()
                 .unwrap()
@@ -244,13 +533,147 @@ mod tests {
             assert_eq!(profile, expected);
         }
 
+        #[test]
+        fn it_overwrites_profile_in_place() {
+            let conf = build().set(Profile::new("default").set("region", "us-west-2"));
+            assert_eq!(
+                conf.format(),
+                "[default]\nregion = us-west-2\n\n[profile test]\nregion = ap-northeast-1\noutput = json\n"
+            );
+        }
+
+        #[test]
+        fn it_inserts_new_profile_after_anchor() {
+            let profile = Profile::new("default-mfa").set("region", "us-east-1");
+            let conf = build().set_after(profile, "default");
+            assert_eq!(
+                conf.format(),
+                "[default]\nregion = us-east-1\noutput = yaml\n\n[profile default-mfa]\nregion = us-east-1\n\n[profile test]\nregion = ap-northeast-1\noutput = json\n"
+            );
+
+            // The comment above the next profile stays there.
+            let profile = Profile::new("default-mfa").set("region", "us-east-1");
+            let commented = ConfLoader::new()
+                .set_path(Path::new("mock/test_commented"))
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .load()
+                .unwrap()
+                .set_after(profile, "default");
+            assert!(commented.format().contains(
+                "output = yaml\n\n[profile default-mfa]\nregion = us-east-1\n\n# The test account\n[profile test]\n"
+            ));
+
+            // The last profile gets a blank line before the new one.
+            let profile = Profile::new("test-mfa").set("region", "us-east-1");
+            let conf = conf.set_after(profile, "test");
+            assert!(conf
+                .format()
+                .ends_with("output = json\n\n[profile test-mfa]\nregion = us-east-1\n"));
+
+            // Unknown anchor appends the profile to the end.
+            let conf = conf.set_after(Profile::new("other"), "unknown");
+            assert!(conf.format().ends_with("\n\n[profile other]\n"));
+        }
+
+        #[test]
+        fn it_sets_and_removes_value() {
+            let conf = build()
+                .set_value("test", "region", "eu-west-1")
+                .set_value("test", "s3.addressing_style", "path")
+                .set_value("new", "output", "text");
+
+            let test = conf.profile("test").unwrap();
+            assert_eq!(test.get("region"), Some("eu-west-1"));
+            assert_eq!(test.get("s3.addressing_style"), Some("path"));
+            assert_eq!(conf.profile("new").unwrap().get("output"), Some("text"));
+            assert!(conf.format().ends_with("[profile new]\noutput = text\n"));
+
+            let conf = conf
+                .remove_value("test", "region")
+                .remove_value("unknown", "region");
+            assert_eq!(conf.profile("test").unwrap().get("region"), None);
+            assert!(conf.profile("unknown").is_none());
+        }
+
+        #[test]
+        fn it_removes_profile() {
+            let conf = build().remove("default");
+            assert!(conf.profile("default").is_none());
+            assert_eq!(
+                conf.format(),
+                "[profile test]\nregion = ap-northeast-1\noutput = json\n"
+            );
+
+            let conf = build().remove("test");
+            assert!(conf.profile("test").is_none());
+            assert_eq!(
+                conf.format(),
+                "[default]\nregion = us-east-1\noutput = yaml\n"
+            );
+
+            let conf = build().remove("unknown");
+            assert_eq!(conf.format(), build().format());
+
+            let conf = build().remove("default").remove("test");
+            assert_eq!(conf.format(), "");
+
+            // Removing from a file without sections is a no-op.
+            let conf = conf.remove("test");
+            assert_eq!(conf.format(), "");
+        }
+
+        #[test]
+        fn it_keeps_comment_of_next_section_on_removal() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("config");
+            std::fs::write(
+                &path,
+                "# Shared settings\n\n[profile alpha-mfa]\n# generated by awsmfa\nregion = us-east-1\n\n# Production account - do not touch\n[profile prod]\nregion = eu-west-1\n\n[profile beta-mfa]\n# generated by awsmfa\n\n# The last comment\n",
+            )
+            .unwrap();
+
+            let conf = ConfLoader::new()
+                .set_path(&path)
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .load()
+                .unwrap()
+                .remove("alpha-mfa")
+                .remove("beta-mfa");
+            assert_eq!(
+                conf.format(),
+                "# Shared settings\n\n# Production account - do not touch\n[profile prod]\nregion = eu-west-1\n\n# The last comment\n"
+            );
+        }
+
+        #[test]
+        fn it_finds_duplicated_sections() {
+            let conf = build();
+            assert!(conf.duplicates().is_empty());
+
+            let conf = ConfFile {
+                profiles: vec![
+                    Profile::new("default"),
+                    Profile::with_kind(SectionKind::SsoSession, "default"),
+                    Profile::new("test"),
+                    Profile::new("default").set_header("[profile default]"),
+                ],
+                ..conf
+            };
+            let duplicates = conf.duplicates();
+            assert_eq!(duplicates.len(), 1);
+            assert_eq!(duplicates[0].name(), "default");
+            assert_eq!(duplicates[0].kind(), SectionKind::Default);
+        }
+
         #[test]
         fn it_regards_same_if_profiles_is_matched() {
             let fmt = Box::new(|p: &str| format!("[{p}]"));
 
             let conf0 = ConfLoader::new()
                 .set_path(Path::new("mock/test_base_equiv"))
-                .set_reg_profile(REG_PROFILE)
+                .set_file_type(FileType::Config)
                 .set_formatter(fmt)
                 .load()
                 .unwrap();
@@ -259,6 +682,188 @@ mod tests {
             assert_eq!(conf0, conf1);
         }
 
+        #[test]
+        fn it_keeps_comments_and_blank_lines() {
+            let path = Path::new("mock/test_commented");
+            let conf = ConfLoader::new()
+                .set_path(path)
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .load()
+                .unwrap();
+
+            let original = std::fs::read_to_string(path).unwrap();
+            assert_eq!(conf.format(), original);
+
+            let profile = conf.profile("test").cloned().unwrap();
+            assert_eq!(profile.get("region"), Some("ap-northeast-1"));
+
+            // Adding a profile only appends lines for it.
+            let conf = conf.set(Profile::new("new").set("region", "us-west-2"));
+            assert_eq!(
+                conf.format(),
+                format!("{original}\n[profile new]\nregion = us-west-2\n")
+            );
+        }
+
+        #[test]
+        fn it_loads_non_profile_sections() {
+            let path = Path::new("mock/test_sections");
+            let conf = ConfLoader::new()
+                .set_path(path)
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .load()
+                .unwrap();
+            assert_eq!(conf.profiles.len(), 5);
+
+            let profile = conf.profile("test").unwrap();
+            assert_eq!(profile.get("sso_start_url"), None);
+            assert_eq!(profile.get("endpoint_url"), None);
+
+            let session = conf.section(SectionKind::SsoSession, "corp").unwrap();
+            assert_eq!(
+                session.get("sso_start_url"),
+                Some("https://example.awsapps.com/start")
+            );
+            assert!(conf.profile("corp").is_none());
+
+            let services = conf.section(SectionKind::Services, "local").unwrap();
+            assert_eq!(
+                services.get("s3.endpoint_url"),
+                Some("http://localhost:4566")
+            );
+
+            let plugins = conf.section(SectionKind::Unknown, "plugins").unwrap();
+            assert_eq!(plugins.get("cli_legacy_plugin_path"), Some("/opt/plugins"));
+
+            // Every section is written back with its own header.
+            let original = std::fs::read_to_string(path).unwrap();
+            let profile = conf.profile("test").cloned().unwrap().rename("test-mfa");
+            let conf = conf.set_after(profile, "test");
+            assert_eq!(
+                conf.format(),
+                original.replace(
+                    "mfa_serial = arn:aws:iam::999999999999:mfa/user\n",
+                    "mfa_serial = arn:aws:iam::999999999999:mfa/user\n\n[profile test-mfa]\nregion = ap-northeast-1\nmfa_serial = arn:aws:iam::999999999999:mfa/user\n"
+                )
+            );
+        }
+
+        #[test]
+        fn it_loads_missing_file_as_empty() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join(".aws/config");
+            let conf = ConfLoader::new()
+                .set_path(&path)
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .load()
+                .unwrap();
+            assert!(conf.profiles.is_empty());
+
+            let conf = conf.set(Profile::new("test").set("region", "us-east-1"));
+            conf.write(&path).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&path).unwrap(),
+                "[profile test]\nregion = us-east-1\n"
+            );
+        }
+
+        #[test]
+        fn it_rejects_unrecognized_lines_in_strict_mode() {
+            let load = |strict: bool| {
+                ConfLoader::new()
+                    .set_path(Path::new("mock/test_credentials"))
+                    .set_file_type(FileType::Credentials)
+                    .set_formatter(Box::new(|p| format!("[{p}]")))
+                    .set_strict(strict)
+                    .load()
+            };
+
+            assert!(load(false).is_ok());
+
+            let err = load(true).unwrap_err();
+            let err = err.downcast_ref::<ParseError>().unwrap();
+            assert_eq!(err.path, Path::new("mock/test_credentials"));
+            assert_eq!(err.line, 6);
+            assert_eq!(err.column, 1);
+            assert_eq!(err.text, "xxxxxxxxxxxxxxxx");
+            assert_eq!(
+                err.to_string(),
+                "mock/test_credentials:6:1: expected \"key = value\": \"xxxxxxxxxxxxxxxx\""
+            );
+        }
+
+        #[test]
+        fn it_checks_headers_by_file_type_in_strict_mode() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("config");
+            let load = |file_type: FileType| {
+                ConfLoader::new()
+                    .set_path(&path)
+                    .set_file_type(file_type)
+                    .set_formatter(Box::new(|p| format!("[{p}]")))
+                    .set_strict(true)
+                    .load()
+            };
+
+            std::fs::write(&path, "[profile \"my profile\"] # comment\nregion = x\n").unwrap();
+            let conf = load(FileType::Config).unwrap();
+            assert!(conf.profile("my profile").is_some());
+            let conf = load(FileType::Credentials).unwrap();
+            assert!(conf.profile("profile \"my profile\"").is_some());
+
+            std::fs::write(&path, "[profile \"unterminated]\nregion = x\n").unwrap();
+            for file_type in [FileType::Config, FileType::Credentials] {
+                let err = load(file_type).unwrap_err();
+                let err = err.downcast_ref::<ParseError>().unwrap();
+                assert_eq!(err.line, 1);
+                assert_eq!(err.message, "invalid section header");
+            }
+        }
+
+        #[test]
+        fn it_accepts_valid_file_in_strict_mode() {
+            let conf = ConfLoader::new()
+                .set_path(Path::new("mock/test_sections"))
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .set_strict(true)
+                .load();
+            assert!(conf.is_ok());
+        }
+
+        #[test]
+        fn it_keeps_crlf_and_bom() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("config");
+            let original =
+                "\u{feff}[profile test]\r\nregion = ap-northeast-1\r\nmfa_serial = arn:aws:iam::999999999999:mfa/user\r\n";
+            std::fs::write(&path, original).unwrap();
+
+            let conf = ConfLoader::new()
+                .set_path(&path)
+                .set_file_type(FileType::Config)
+                .set_formatter(Box::new(|p| format!("[profile {p}]")))
+                .load()
+                .unwrap();
+
+            let profile = conf.profile("test").cloned().unwrap();
+            assert_eq!(
+                profile.get("mfa_serial"),
+                Some("arn:aws:iam::999999999999:mfa/user")
+            );
+            assert_eq!(conf.format(), original);
+
+            let conf = conf.set(Profile::new("new").set("region", "us-west-2"));
+            conf.write(&path).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&path).unwrap(),
+                format!("{original}\r\n[profile new]\r\nregion = us-west-2\r\n")
+            );
+        }
+
         #[test]
         fn it_writes_to_file() {
             let path = Path::new("mock/write_test_base");
@@ -268,7 +873,7 @@ mod tests {
 
             let conf1 = ConfLoader::new()
                 .set_path(path)
-                .set_reg_profile(REG_PROFILE)
+                .set_file_type(FileType::Config)
                 .set_formatter(Box::new(|p| format!("[{p}]")))
                 .load()
                 .unwrap();
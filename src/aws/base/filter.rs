@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// The keys which must not be copied from the source profile by default. They make the
+/// generated profile assume a role, run a process or use SSO instead of the session
+/// credentials.
+const DEFAULT_DENY: [&str; 13] = [
+    "mfa_serial",
+    "role_arn",
+    "source_profile",
+    "credential_source",
+    "credential_process",
+    "external_id",
+    "role_session_name",
+    "duration_seconds",
+    "web_identity_token_file",
+    "sso_*",
+    "aws_access_key_id",
+    "aws_secret_access_key",
+    "aws_session_token",
+];
+
+/// Which keys of the source profile are copied to the generated profile. A pattern
+/// matches the key exactly or, ending with `*`, by prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeyFilter {
+    /// Copy only these keys when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allow: Option<Vec<String>>,
+
+    /// Never copy these keys, even if they are allowed.
+    #[serde(default = "default_deny")]
+    deny: Vec<String>,
+}
+
+impl Default for KeyFilter {
+    fn default() -> Self {
+        Self {
+            allow: None,
+            deny: default_deny(),
+        }
+    }
+}
+
+impl KeyFilter {
+    pub fn new(allow: Option<Vec<String>>, deny: Vec<String>) -> Self {
+        Self { allow, deny }
+    }
+
+    pub fn is_copied(&self, key: &str) -> bool {
+        let allowed = match &self.allow {
+            Some(patterns) => patterns.iter().any(|p| matches(p, key)),
+            None => true,
+        };
+
+        allowed && !self.deny.iter().any(|p| matches(p, key))
+    }
+}
+
+fn matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => pattern == key,
+    }
+}
+
+fn default_deny() -> Vec<String> {
+    DEFAULT_DENY.iter().map(|k| k.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_denies_unsafe_keys_by_default() {
+        let filter = KeyFilter::default();
+        assert!(filter.is_copied("region"));
+        assert!(filter.is_copied("s3"));
+        assert!(!filter.is_copied("mfa_serial"));
+        assert!(!filter.is_copied("role_arn"));
+        assert!(!filter.is_copied("sso_start_url"));
+        assert!(!filter.is_copied("credential_process"));
+        assert!(!filter.is_copied("duration_seconds"));
+    }
+
+    #[test]
+    fn it_copies_only_allowed_keys() {
+        let filter = KeyFilter::new(Some(vec!["region".into(), "cli_*".into()]), vec![]);
+        assert!(filter.is_copied("region"));
+        assert!(filter.is_copied("cli_pager"));
+        assert!(!filter.is_copied("output"));
+
+        let filter = KeyFilter::new(Some(vec!["*".into()]), vec!["output".into()]);
+        assert!(filter.is_copied("region"));
+        assert!(!filter.is_copied("output"));
+    }
+
+    #[test]
+    fn it_reads_yaml() {
+        let filter: KeyFilter = serde_yaml::from_str("allow:\n  - region\n").unwrap();
+        assert_eq!(
+            filter,
+            KeyFilter::new(Some(vec!["region".into()]), default_deny())
+        );
+
+        let filter: KeyFilter = serde_yaml::from_str("deny: []\n").unwrap();
+        assert_eq!(filter, KeyFilter::new(None, vec![]));
+    }
+}
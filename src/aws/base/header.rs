@@ -0,0 +1,200 @@
+use super::{FileType, SectionKind};
+
+/// Parse the section header the way the AWS CLI does. Whitespace around the brackets
+/// and the words is ignored, names can be quoted and a comment can follow the header.
+///
+/// In the config file, `[profile name]` is a profile and `[profile default]` is the
+/// default profile. In the credentials file, every section is a profile named by the
+/// whole text in the brackets.
+pub fn parse(line: &str, file_type: FileType) -> Option<(SectionKind, String)> {
+    let inner = brackets(line)?;
+
+    if inner == "default" {
+        return Some((SectionKind::Default, inner.into()));
+    }
+
+    if file_type == FileType::Credentials {
+        return Some((SectionKind::Profile, inner.into()));
+    }
+
+    let section = match tokenize(inner).as_deref() {
+        Some([prefix, name]) => match prefix.as_str() {
+            "profile" if name == "default" => Some((SectionKind::Default, name.clone())),
+            "profile" => Some((SectionKind::Profile, name.clone())),
+            "sso-session" => Some((SectionKind::SsoSession, name.clone())),
+            "services" => Some((SectionKind::Services, name.clone())),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    section.or(Some((SectionKind::Unknown, inner.into())))
+}
+
+/// Quote the name if it can't be written as a bare word. A bare `]` would end the
+/// header early.
+pub fn quote(name: &str) -> String {
+    if name.is_empty()
+        || name.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ']'))
+    {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        name.into()
+    }
+}
+
+/// The trimmed text between the brackets.
+fn brackets(line: &str) -> Option<&str> {
+    let line = line.trim().strip_prefix('[')?;
+    let mut quote: Option<char> = None;
+
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ']') => {
+                let rest = line[i + 1..].trim_start();
+                let inner = line[..i].trim();
+
+                return if (rest.is_empty() || rest.starts_with(['#', ';'])) && !inner.is_empty() {
+                    Some(inner)
+                } else {
+                    None
+                };
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Split the text into words by whitespace. A quoted word can contain whitespace and
+/// a backslash escapes the next character in double quotes.
+fn tokenize(text: &str) -> Option<Vec<String>> {
+    let mut tokens: Vec<String> = vec![];
+    let mut token: Option<String> = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                let word = token.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\\' if c == '"' => word.push(chars.next()?),
+                        q if q == c => break,
+                        ch => word.push(ch),
+                    }
+                }
+            }
+            c if c.is_whitespace() => tokens.extend(token.take()),
+            c => token.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    tokens.extend(token);
+    Some(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(line: &str) -> Option<(SectionKind, String)> {
+        parse(line, FileType::Config)
+    }
+
+    fn credentials(line: &str) -> Option<(SectionKind, String)> {
+        parse(line, FileType::Credentials)
+    }
+
+    #[test]
+    fn it_parses_config_headers() {
+        use SectionKind::*;
+
+        assert_eq!(config("[default]"), Some((Default, "default".into())));
+        assert_eq!(
+            config("[profile default]"),
+            Some((Default, "default".into()))
+        );
+        assert_eq!(config("[profile alpha]"), Some((Profile, "alpha".into())));
+        assert_eq!(
+            config("  [ profile   alpha ]  "),
+            Some((Profile, "alpha".into()))
+        );
+        assert_eq!(
+            config("[profile \"my profile\"]"),
+            Some((Profile, "my profile".into()))
+        );
+        assert_eq!(
+            config("[profile 'my profile']"),
+            Some((Profile, "my profile".into()))
+        );
+        assert_eq!(
+            config("[profile alpha] # production"),
+            Some((Profile, "alpha".into()))
+        );
+        assert_eq!(
+            config("[sso-session corp]"),
+            Some((SsoSession, "corp".into()))
+        );
+        assert_eq!(config("[services local]"), Some((Services, "local".into())));
+        assert_eq!(config("[plugins]"), Some((Unknown, "plugins".into())));
+        assert_eq!(config("[alpha]"), Some((Unknown, "alpha".into())));
+        assert_eq!(
+            config("[profile a b]"),
+            Some((Unknown, "profile a b".into()))
+        );
+    }
+
+    #[test]
+    fn it_parses_credentials_headers() {
+        use SectionKind::*;
+
+        assert_eq!(credentials("[default]"), Some((Default, "default".into())));
+        assert_eq!(credentials("[ alpha ]"), Some((Profile, "alpha".into())));
+        assert_eq!(
+            credentials("[profile alpha]"),
+            Some((Profile, "profile alpha".into()))
+        );
+    }
+
+    #[test]
+    fn it_rejects_non_header_lines() {
+        assert_eq!(config("region = us-east-1"), None);
+        assert_eq!(config("[]"), None);
+        assert_eq!(config("[profile alpha"), None);
+        assert_eq!(config("[profile alpha] region"), None);
+        assert_eq!(config("[profile \"unterminated]"), None);
+        assert_eq!(config("# [profile alpha]"), None);
+    }
+
+    #[test]
+    fn it_quotes_names() {
+        assert_eq!(quote("alpha"), "alpha");
+        assert_eq!(quote("my profile"), "\"my profile\"");
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote("a]b"), "\"a]b\"");
+    }
+
+    #[test]
+    fn it_reads_quoted_names_back() {
+        for name in [
+            "alpha",
+            "my profile",
+            "a]b",
+            "say \"hi\"",
+            "back\\slash x",
+            "[x]",
+        ] {
+            let header = format!("[profile {}]", quote(name));
+            assert_eq!(
+                config(&header),
+                Some((SectionKind::Profile, name.to_string())),
+                "{header}"
+            );
+        }
+    }
+}
@@ -1,5 +1,11 @@
+mod error;
 mod file;
+mod filter;
+mod header;
 mod profile;
 
-pub use file::{ConfFile, ConfLoader};
-pub use profile::Profile;
+pub use error::ParseError;
+pub use file::{ConfFile, ConfLoader, FileType, Placement};
+pub use filter::KeyFilter;
+pub use header::quote;
+pub use profile::{Profile, SectionKind};
@@ -1,19 +1,63 @@
 use std::collections::HashMap;
 
+/// The kind of a section in the AWS config or credentials file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    /// `[default]`
+    Default,
+    /// `[profile name]` in config, `[name]` in credentials
+    Profile,
+    /// `[sso-session name]`
+    SsoSession,
+    /// `[services name]`
+    Services,
+    /// Any other section such as `[plugins]`
+    Unknown,
+}
+
+impl SectionKind {
+    /// Whether the section is a profile which can be looked up by its name.
+    pub fn is_profile(&self) -> bool {
+        matches!(self, Self::Default | Self::Profile)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Profile {
+    kind: SectionKind,
     name: String,
+    header: Option<String>,
     lines: Vec<String>,
 }
 
 impl Profile {
     pub fn new(name: &str) -> Self {
+        let kind = if name == "default" {
+            SectionKind::Default
+        } else {
+            SectionKind::Profile
+        };
+
+        Self::with_kind(kind, name)
+    }
+
+    pub fn with_kind(kind: SectionKind, name: &str) -> Self {
         Self {
+            kind,
             name: name.into(),
+            header: None,
             lines: vec![],
         }
     }
 
+    /// Keep the section header exactly as it was written in the file.
+    pub fn set_header(self, header: &str) -> Self {
+        Self {
+            header: Some(header.into()),
+            ..self
+        }
+    }
+
     pub fn push(self, line: &str) -> Self {
         let mut lines = self.lines;
         lines.push(line.into());
@@ -25,59 +69,344 @@ impl Profile {
         &self.name
     }
 
+    pub fn kind(&self) -> SectionKind {
+        self.kind
+    }
+
     pub fn rename(self, name: &str) -> Self {
+        let kind = match self.kind {
+            SectionKind::Default | SectionKind::Profile => Self::new(name).kind,
+            kind => kind,
+        };
+
         Self {
+            kind,
             name: name.into(),
+            header: None,
             ..self
         }
     }
 
+    /// Format the section. The formatter makes the header of a profile section which
+    /// doesn't have the original one.
     pub fn format<F: Fn(&str) -> String>(&self, f: F) -> String {
-        format!("{}\n{}", f(&self.name), self.lines.join("\n"))
+        let header = match (&self.header, self.kind) {
+            (Some(header), _) => header.to_string(),
+            (None, SectionKind::SsoSession) => format!("[sso-session {}]", self.name),
+            (None, SectionKind::Services) => format!("[services {}]", self.name),
+            (None, SectionKind::Unknown) => format!("[{}]", self.name),
+            (None, _) => f(&self.name),
+        };
+
+        std::iter::once(header)
+            .chain(self.lines.iter().cloned())
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
-    pub fn get(&self, key: &str) -> Option<&str> {
-        self.pairs().get(key).copied()
+    /// Whether awsmfa generated this profile, which it tells by the marker comment.
+    pub fn is_generated(&self) -> bool {
+        self.lines.iter().any(|l| l.trim() == GENERATED_MARKER)
     }
 
-    pub fn set(self, key: &str, value: &str) -> Self {
-        let mut lines = self.remove_line(key);
-        lines.push(format!("{key} = {value}"));
+    /// Put the marker comment on the first line unless it's already there.
+    pub fn mark_generated(self) -> Self {
+        if self.is_generated() {
+            return self;
+        }
+
+        let mut lines = self.lines;
+        lines.insert(0, GENERATED_MARKER.into());
 
         Self { lines, ..self }
     }
 
-    pub fn remove(self, key: &str) -> Self {
+    /// Build the profile that takes the place of this one. The new profile keeps the
+    /// original header and the trailer of this profile.
+    pub fn replace_with(&self, profile: Profile) -> Profile {
+        let header = profile.header.clone().or_else(|| self.header.clone());
+        let (profile, _) = profile.split_trailer();
+        let mut lines = profile.lines;
+
+        lines.extend_from_slice(&self.lines[self.trailer_start()..]);
+
         Self {
-            lines: self.remove_line(key),
-            ..self
+            kind: self.kind,
+            name: self.name.clone(),
+            header,
+            lines,
+        }
+    }
+
+    /// Split off the trailer, the comment and blank lines at the end of this profile. The
+    /// loader puts the comments just above a header there, but they belong to the next
+    /// section.
+    pub fn split_trailer(self) -> (Self, Vec<String>) {
+        let index = self.trailer_start();
+        let mut lines = self.lines;
+        let trailer = lines.split_off(index);
+
+        (Self { lines, ..self }, trailer)
+    }
+
+    /// The index just after the last key line. Without keys, the comments right under the
+    /// header belong to this profile and the trailer starts at the first blank line.
+    fn trailer_start(&self) -> usize {
+        match self
+            .lines
+            .iter()
+            .rposition(|l| !l.trim().is_empty() && !is_comment(l))
+        {
+            Some(i) => i + 1,
+            None => self
+                .lines
+                .iter()
+                .position(|l| l.trim().is_empty())
+                .unwrap_or(self.lines.len()),
+        }
+    }
+
+    /// Drop the blank lines at the end of this profile.
+    pub fn trim_end(self) -> Self {
+        let mut lines = self.lines;
+
+        while lines.last().map(|l| l.trim().is_empty()).unwrap_or(false) {
+            lines.pop();
         }
+
+        Self { lines, ..self }
     }
 
-    fn remove_line(&self, key: &str) -> Vec<String> {
-        self.lines
-            .clone()
+    /// Get the value of the key. Use a dotted path such as `s3.max_concurrent_requests`
+    /// for a nested sub-property.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .into_iter()
+            .find(|e| e.key == key)
+            .map(|e| e.value)
+    }
+
+    /// Set the value of the key. An existing key is overwritten on the same line keeping
+    /// its formatting, and a new key is appended.
+    pub fn set(self, key: &str, value: &str) -> Self {
+        if let Some(index) = self
+            .entries()
+            .iter()
+            .find(|e| e.key == key)
+            .map(|e| e.index)
+        {
+            let mut lines = self.lines;
+            lines[index] = replace_value(&lines[index], value);
+
+            // Drop the duplicated keys so that the value is not shadowed.
+            return Self { lines, ..self }.remove_duplicates(key, index);
+        }
+
+        match key.split_once('.') {
+            Some((parent, child)) => self.set_nested(parent, child, value),
+            None => {
+                let index = self.trailer_start();
+                let mut lines = self.lines;
+                lines.insert(index, format!("{key} = {value}"));
+
+                Self { lines, ..self }
+            }
+        }
+    }
+
+    /// The top-level keys in the order of the lines.
+    pub fn keys(&self) -> Vec<String> {
+        self.entries()
             .into_iter()
-            .filter(|line| match line.split_once('=') {
-                Some((k, _)) => k.trim() != key,
-                None => true,
-            })
+            .map(|e| e.key)
+            .filter(|k| !k.contains('.'))
             .collect()
     }
 
-    fn pairs(&self) -> HashMap<&str, &str> {
-        let iterator = self
+    /// Keep only the top-level keys the predicate accepts, along with their nested values.
+    pub fn retain_keys<F: Fn(&str) -> bool>(self, f: F) -> Self {
+        self.keys()
+            .into_iter()
+            .filter(|k| !f(k))
+            .fold(self, |profile, k| profile.remove(&k))
+    }
+
+    /// Remove the key. Removing a parent key also removes its sub-properties.
+    pub fn remove(self, key: &str) -> Self {
+        let prefix = format!("{key}.");
+        let indices = self
+            .entries()
+            .into_iter()
+            .filter(|e| e.key == key || e.key.starts_with(&prefix))
+            .map(|e| e.index)
+            .collect::<Vec<usize>>();
+
+        let lines = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !indices.contains(i))
+            .map(|(_, line)| line.clone())
+            .collect();
+
+        Self { lines, ..self }
+    }
+
+    fn remove_duplicates(self, key: &str, keep: usize) -> Self {
+        let indices = self
+            .entries()
+            .into_iter()
+            .filter(|e| e.key == key && e.index != keep)
+            .map(|e| e.index)
+            .collect::<Vec<usize>>();
+
+        let lines = self
             .lines
             .iter()
-            .filter_map(|line| line.split_once('=').map(|(k, v)| (k.trim(), v.trim())));
+            .enumerate()
+            .filter(|(i, _)| !indices.contains(i))
+            .map(|(_, line)| line.clone())
+            .collect();
+
+        Self { lines, ..self }
+    }
+
+    fn set_nested(self, parent: &str, child: &str, value: &str) -> Self {
+        let prefix = format!("{parent}.");
+        let entries = self.entries();
+        let mut lines = self.lines.clone();
+
+        if let Some(p) = entries.iter().find(|e| e.key == parent) {
+            let siblings = entries
+                .iter()
+                .filter(|e| e.key.starts_with(&prefix))
+                .collect::<Vec<&Entry>>();
+            let indent = siblings
+                .first()
+                .map(|e| indent_of(&self.lines[e.index]))
+                .unwrap_or(DEFAULT_INDENT);
+            let index = siblings.last().map(|e| e.index).unwrap_or(p.index) + 1;
+            lines.insert(index, format!("{indent}{child} = {value}"));
+        } else {
+            let index = self.trailer_start();
+            lines.insert(index, format!("{parent} ="));
+            lines.insert(index + 1, format!("{DEFAULT_INDENT}{child} = {value}"));
+        }
+
+        Self { lines, ..self }
+    }
+
+    /// Parse the lines into key-value entries. An indented line following a key without
+    /// value is a sub-property of that key and its key is the dotted path. Comments are
+    /// skipped as the AWS CLI does.
+    fn entries(&self) -> Vec<Entry<'_>> {
+        let mut entries: Vec<Entry> = vec![];
+        let mut parent: Option<&str> = None;
+
+        for (index, line) in self.lines.iter().enumerate() {
+            if is_comment(line) {
+                continue;
+            }
+
+            let (k, v) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), strip_comment(v).trim()),
+                None => continue,
+            };
+
+            let key = match parent {
+                Some(p) if line.starts_with(char::is_whitespace) => format!("{p}.{k}"),
+                _ => {
+                    parent = if v.is_empty() { Some(k) } else { None };
+                    k.to_string()
+                }
+            };
+
+            entries.push(Entry {
+                key,
+                value: v,
+                index,
+            });
+        }
+
+        entries
+    }
 
-        HashMap::from_iter(iterator)
+    fn pairs(&self) -> HashMap<String, &str> {
+        HashMap::from_iter(self.entries().into_iter().map(|e| (e.key, e.value)))
     }
 }
 
+const DEFAULT_INDENT: &str = "  ";
+
+/// The comment awsmfa puts on the sections it generates.
+const GENERATED_MARKER: &str = "# generated by awsmfa";
+
+struct Entry<'a> {
+    key: String,
+    value: &'a str,
+    index: usize,
+}
+
+/// Replace the value of the `key = value` line keeping the spacing and the inline
+/// comment.
+fn replace_value(line: &str, value: &str) -> String {
+    let (key, rest) = match line.split_once('=') {
+        Some(pair) => pair,
+        None => return line.into(),
+    };
+
+    let current = strip_comment(rest);
+    let comment = &rest[current.len()..];
+    let leading = &current[..current.len() - current.trim_start().len()];
+    let trailing = if comment.is_empty() {
+        ""
+    } else {
+        &current[current.trim_end().len()..]
+    };
+
+    format!("{key}={leading}{value}{trailing}{comment}")
+}
+
+/// The reason why the line can't be a line of a section, if any.
+pub fn invalid_line(line: &str) -> Option<&'static str> {
+    if line.trim().is_empty() || is_comment(line) {
+        return None;
+    }
+
+    if line.trim_start().starts_with('[') {
+        return Some("invalid section header");
+    }
+
+    match line.split_once('=') {
+        Some((k, _)) if k.trim().is_empty() => Some("missing key"),
+        Some(_) => None,
+        None => Some("expected \"key = value\""),
+    }
+}
+
+/// A line starting with `#` or `;` is a comment.
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with(['#', ';'])
+}
+
+/// Strip the inline comment from the value. A `#` or `;` starts the comment only when
+/// it follows whitespace, so values like `abc#123` are kept as they are.
+fn strip_comment(value: &str) -> &str {
+    value
+        .char_indices()
+        .find(|&(i, c)| (c == '#' || c == ';') && value[..i].ends_with(char::is_whitespace))
+        .map(|(i, _)| &value[..i])
+        .unwrap_or(value)
+}
+
+fn indent_of(line: &str) -> &str {
+    let len = line.len() - line.trim_start().len();
+    &line[..len]
+}
+
 impl PartialEq for Profile {
     fn eq(&self, other: &Self) -> bool {
-        if self.name != other.name {
+        if self.kind != other.kind || self.name != other.name {
             return false;
         }
 
@@ -88,7 +417,7 @@ impl PartialEq for Profile {
             return false;
         }
 
-        self_pairs.iter().all(|(&k, &v)| match other_pairs.get(k) {
+        self_pairs.iter().all(|(k, &v)| match other_pairs.get(k) {
             Some(&_v) => v == _v,
             None => false,
         })
@@ -112,6 +441,97 @@ mod tests {
         assert_eq!(p.format(fmt), "[test]\nregion = us-east-1\noutput = json");
     }
 
+    #[test]
+    fn it_formats_with_original_header() {
+        let p = build().set_header("[ profile   test ]").push("");
+        let fmt = |p: &str| format!("[{p}]");
+        assert_eq!(
+            p.format(fmt),
+            "[ profile   test ]\nregion = us-east-1\noutput = json\n"
+        );
+
+        // renaming drops the original header
+        let p = p.rename("test_v2");
+        assert_eq!(
+            p.format(fmt),
+            "[test_v2]\nregion = us-east-1\noutput = json\n"
+        );
+    }
+
+    #[test]
+    fn it_formats_header_by_kind() {
+        let fmt = |p: &str| format!("[profile {p}]");
+
+        let p = Profile::with_kind(SectionKind::SsoSession, "corp");
+        assert_eq!(p.format(fmt), "[sso-session corp]");
+
+        let p = Profile::with_kind(SectionKind::Services, "local");
+        assert_eq!(p.format(fmt), "[services local]");
+
+        let p = Profile::with_kind(SectionKind::Unknown, "plugins");
+        assert_eq!(p.format(fmt), "[plugins]");
+
+        let p = Profile::new("test");
+        assert_eq!(p.kind(), SectionKind::Profile);
+        assert_eq!(p.format(fmt), "[profile test]");
+
+        let p = p.rename("default");
+        assert_eq!(p.kind(), SectionKind::Default);
+    }
+
+    #[test]
+    fn it_replaces_with_original_header_and_blank_lines() {
+        let old = Profile::new("test")
+            .set_header("[profile   test]")
+            .set("region", "us-east-1")
+            .push("")
+            .push("");
+        let new = Profile::new("test").set("region", "us-west-2");
+
+        let fmt = |p: &str| format!("[profile {p}]");
+        assert_eq!(
+            old.replace_with(new).format(fmt),
+            "[profile   test]\nregion = us-west-2\n\n"
+        );
+    }
+
+    #[test]
+    fn it_keeps_comment_of_next_section_in_trailer() {
+        let p = build().push("").push("# The next account");
+        let fmt = |p: &str| format!("[{p}]");
+
+        // New keys go above the comment, which belongs to the next section.
+        let p = p.set("cli_pager", "");
+        assert_eq!(
+            p.format(fmt),
+            "[test]\nregion = us-east-1\noutput = json\ncli_pager = \n\n# The next account"
+        );
+
+        let (p, trailer) = p.split_trailer();
+        assert_eq!(trailer, vec!["", "# The next account"]);
+        assert_eq!(
+            p.format(fmt),
+            "[test]\nregion = us-east-1\noutput = json\ncli_pager = "
+        );
+
+        // Without keys, the comments right under the header stay.
+        let (p, trailer) = Profile::new("test")
+            .mark_generated()
+            .push("")
+            .push("# The next account")
+            .split_trailer();
+        assert!(p.is_generated());
+        assert_eq!(trailer, vec!["", "# The next account"]);
+    }
+
+    #[test]
+    fn it_trims_trailing_blank_lines() {
+        let p = build().push("").push("  ").trim_end();
+
+        let fmt = |p: &str| format!("[{p}]");
+        assert_eq!(p.format(fmt), "[test]\nregion = us-east-1\noutput = json");
+    }
+
     #[test]
     fn it_gets_value_from_key() {
         let p = build();
@@ -134,6 +554,26 @@ mod tests {
         assert_eq!(p.get("foo"), Some("foobar"));
     }
 
+    #[test]
+    fn it_overwrites_value_in_place() {
+        let p = Profile::new("test")
+            .push("aws_access_key_id=OLD_KEY")
+            .push("aws_session_token   =   old_token # refreshed by awsmfa")
+            .push("custom_key = custom")
+            .push("aws_access_key_id = DUPLICATED");
+
+        let p = p
+            .set("aws_session_token", "new_token")
+            .set("aws_access_key_id", "NEW_KEY")
+            .set("region", "us-east-1");
+
+        let fmt = |p: &str| format!("[{p}]");
+        assert_eq!(
+            p.format(fmt),
+            "[test]\naws_access_key_id=NEW_KEY\naws_session_token   =   new_token # refreshed by awsmfa\ncustom_key = custom\nregion = us-east-1"
+        );
+    }
+
     #[test]
     fn it_removes_value() {
         let p = build();
@@ -143,6 +583,143 @@ mod tests {
         assert_eq!(p.get("region"), None);
     }
 
+    #[test]
+    fn it_keeps_new_key_before_trailing_blank_lines() {
+        let p = build().push("").set("foo", "bar");
+        let fmt = |p: &str| format!("[{p}]");
+        assert_eq!(
+            p.format(fmt),
+            "[test]\nregion = us-east-1\noutput = json\nfoo = bar\n"
+        );
+    }
+
+    #[test]
+    fn it_handles_nested_keys() {
+        let p = build()
+            .push("s3 =")
+            .push("    max_concurrent_requests = 20")
+            .push("    max_queue_size = 10000")
+            .push("max_attempts = 3");
+
+        assert_eq!(p.get("s3"), Some(""));
+        assert_eq!(p.get("s3.max_concurrent_requests"), Some("20"));
+        assert_eq!(p.get("s3.max_queue_size"), Some("10000"));
+        assert_eq!(p.get("max_concurrent_requests"), None);
+        assert_eq!(p.get("max_attempts"), Some("3"));
+
+        let fmt = |p: &str| format!("[{p}]");
+
+        // it overwrites and adds sub-properties inside the nested block
+        let p = p
+            .set("s3.max_concurrent_requests", "30")
+            .set("s3.multipart_threshold", "64MB");
+        assert_eq!(
+            p.format(fmt),
+            "[test]\nregion = us-east-1\noutput = json\ns3 =\n    max_concurrent_requests = 30\n    max_queue_size = 10000\n    multipart_threshold = 64MB\nmax_attempts = 3"
+        );
+
+        // it removes only the sub-property
+        let p = p.remove("s3.max_queue_size");
+        assert_eq!(p.get("s3.max_queue_size"), None);
+        assert_eq!(p.get("s3.max_concurrent_requests"), Some("30"));
+
+        // it removes the parent with all of its sub-properties
+        let p = p.remove("s3");
+        assert_eq!(
+            p.format(fmt),
+            "[test]\nregion = us-east-1\noutput = json\nmax_attempts = 3"
+        );
+
+        // it adds a new nested block
+        let p = p.set("sts.endpoint_url", "http://localhost");
+        assert_eq!(p.get("sts.endpoint_url"), Some("http://localhost"));
+        assert!(p
+            .format(fmt)
+            .ends_with("max_attempts = 3\nsts =\n  endpoint_url = http://localhost"));
+    }
+
+    #[test]
+    fn it_lists_top_level_keys() {
+        let p = build()
+            .push("# foo = bar")
+            .push("s3 =")
+            .push("  max_concurrent_requests = 20");
+        assert_eq!(p.keys(), vec!["region", "output", "s3"]);
+    }
+
+    #[test]
+    fn it_retains_keys() {
+        let p = build()
+            .push("s3 =")
+            .push("  max_concurrent_requests = 20")
+            .retain_keys(|k| k != "output");
+        assert_eq!(p.keys(), vec!["region", "s3"]);
+        assert_eq!(p.get("s3.max_concurrent_requests"), Some("20"));
+    }
+
+    #[test]
+    fn it_marks_generated_profile() {
+        let p = build();
+        assert!(!p.is_generated());
+
+        let p = p.mark_generated().mark_generated();
+        assert!(p.is_generated());
+        assert_eq!(
+            p.format(|n| format!("[{n}]")),
+            "[test]\n# generated by awsmfa\nregion = us-east-1\noutput = json"
+        );
+        assert_eq!(p, build());
+    }
+
+    #[test]
+    fn it_skips_comments() {
+        let p = Profile::new("test")
+            .push("# mfa_serial = arn:aws:iam::999999999999:mfa/old")
+            .push(";mfa_serial=arn:aws:iam::999999999999:mfa/older")
+            .push("region = us-east-1 # prod")
+            .push("output = json ; default")
+            .push("aws_secret_access_key = abc#def;ghi")
+            .push("s3 = # nested")
+            .push("  # max_concurrent_requests = 10")
+            .push("  max_concurrent_requests = 20");
+
+        assert_eq!(p.get("mfa_serial"), None);
+        assert_eq!(p.get("# mfa_serial"), None);
+        assert_eq!(p.get(";mfa_serial"), None);
+        assert_eq!(p.get("region"), Some("us-east-1"));
+        assert_eq!(p.get("output"), Some("json"));
+        assert_eq!(p.get("aws_secret_access_key"), Some("abc#def;ghi"));
+        assert_eq!(p.get("s3.max_concurrent_requests"), Some("20"));
+
+        // comments are written back as they are
+        let fmt = |p: &str| format!("[{p}]");
+        let formatted = p.clone().remove("output").format(fmt);
+        assert!(formatted.contains("# mfa_serial = arn:aws:iam::999999999999:mfa/old\n"));
+        assert!(formatted.contains("region = us-east-1 # prod\n"));
+        assert!(!formatted.contains("output"));
+
+        // removing the key doesn't remove the commented out one
+        let formatted = p.remove("mfa_serial").format(fmt);
+        assert!(formatted.contains(";mfa_serial=arn:aws:iam::999999999999:mfa/older"));
+    }
+
+    #[test]
+    fn it_finds_invalid_lines() {
+        assert_eq!(invalid_line("region = us-east-1"), None);
+        assert_eq!(invalid_line("  max_concurrent_requests = 20"), None);
+        assert_eq!(invalid_line("# comment"), None);
+        assert_eq!(invalid_line(""), None);
+        assert_eq!(
+            invalid_line("xxxxxxxxxxxxxxxx"),
+            Some("expected \"key = value\"")
+        );
+        assert_eq!(invalid_line(" = value"), Some("missing key"));
+        assert_eq!(
+            invalid_line("[profile test"),
+            Some("invalid section header")
+        );
+    }
+
     #[test]
     fn it_equals_when_profile_and_key_values_are_all_matched() {
         let p0 = build();
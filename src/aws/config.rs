@@ -1,79 +1,241 @@
 use crate::Result;
 
-use super::{aws_home, ConfFile, ConfLoader};
+use super::{
+    base::quote, filepath, ConfFile, ConfLoader, FileType, KeyFilter, Placement, SessionDuration,
+    SECRET_KEYS,
+};
 use anyhow::anyhow;
 use std::path::{Path, PathBuf};
 
-const PROFILE: &str = r"^\[profile\s+(.+)\]$";
-const FILENAME: &str = "config";
+pub(super) const FILENAME: &str = "config";
+pub(super) const ENV_PATH: &str = "AWS_CONFIG_FILE";
 const MFA_SERIAL: &str = "mfa_serial";
+const SOURCE_PROFILE: &str = "source_profile";
+const DURATION_SECONDS: &str = "duration_seconds";
+
+/// A profile resolved through its `source_profile` chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedProfile {
+    /// The profiles from the resolved one to the base one, which has the credentials.
+    pub chain: Vec<String>,
+    /// The `mfa_serial` of the nearest profile in the chain which has one.
+    pub mfa_serial: String,
+}
+
+impl ResolvedProfile {
+    pub fn base(&self) -> &str {
+        self.chain.last().map(String::as_str).unwrap_or_default()
+    }
+
+    /// The nearest profile in the chain for which awsmfa.yml has the secret.
+    pub fn secret_profile<F: Fn(&str) -> bool>(&self, has_secret: F) -> Option<&str> {
+        self.chain
+            .iter()
+            .map(String::as_str)
+            .find(|name| has_secret(name))
+    }
+}
 
 #[derive(Debug)]
 pub struct Config {
+    path: PathBuf,
     content: ConfFile,
 }
 
 impl Config {
-    pub fn new() -> Result<Self> {
-        let path = filepath()?;
-        Self::load(path.as_path())
+    pub fn new(strict: bool) -> Result<Self> {
+        let path = filepath(ENV_PATH, FILENAME)?;
+        Self::load(path.as_path(), strict)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn content(&self) -> &ConfFile {
+        &self.content
     }
 
-    pub fn mfa_serial(&self, profile: &str) -> Result<&str> {
-        self.content
+    /// The `mfa_serial` of the profile, inherited from its source profiles if it doesn't
+    /// have one.
+    pub fn mfa_serial(&self, profile: &str) -> Result<String> {
+        self.resolve(profile).map(|r| r.mfa_serial)
+    }
+
+    /// The session duration set by `duration_seconds` of the profile. The AWS CLI reads
+    /// the key as integer seconds, so the unit forms of the duration option are rejected.
+    pub fn duration(&self, profile: &str) -> Result<Option<SessionDuration>> {
+        let value = match self
+            .content
             .profile(profile)
+            .and_then(|p| p.get(DURATION_SECONDS))
+        {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        value
+            .parse::<i64>()
+            .map_err(|_| anyhow!("It must be integer seconds such as 3600."))
+            .and_then(SessionDuration::new)
+            .map(Some)
+            .map_err(|err| {
+                anyhow!(
+                    "Invalid {} in profile {} at {}. {}",
+                    DURATION_SECONDS,
+                    profile,
+                    self.path.to_string_lossy(),
+                    err
+                )
+            })
+    }
+
+    /// Follow the `source_profile` links from the profile to the base one to find the
+    /// nearest `mfa_serial`.
+    pub fn resolve(&self, profile: &str) -> Result<ResolvedProfile> {
+        if self.content.profile(profile).is_none() {
+            return Err(self.profile_not_found(profile));
+        }
+
+        let chain = self.source_chain(profile).map_err(|cycle| {
+            anyhow!(
+                "Source profiles form a cycle: {} at {}",
+                cycle.join(" -> "),
+                self.path.to_string_lossy(),
+            )
+        })?;
+
+        let mfa_serial = chain
+            .iter()
+            .find_map(|name| self.content.profile(name)?.get(MFA_SERIAL))
             .ok_or(anyhow!(
-                "Not Found profile: {} at {}",
-                profile,
-                filepath()?.to_string_lossy(),
-            ))?
-            .get(MFA_SERIAL)
-            .ok_or(anyhow!(
-                "Not Found mfa_serial in profile {} at {}",
+                "Not Found mfa_serial in profile {} or its source profiles at {}",
                 profile,
-                filepath()?.to_string_lossy(),
-            ))
+                self.path.to_string_lossy(),
+            ))?;
+
+        Ok(ResolvedProfile {
+            chain: chain.into_iter().map(String::from).collect(),
+            mfa_serial: mfa_serial.into(),
+        })
+    }
+
+    /// The profiles from the profile to the base one by the `source_profile` links. A
+    /// profile which uses itself as the source, or whose source isn't in this file, is
+    /// the base one. The error has the links which form a cycle.
+    pub(super) fn source_chain<'a>(
+        &'a self,
+        profile: &'a str,
+    ) -> std::result::Result<Vec<&'a str>, Vec<&'a str>> {
+        let mut chain = vec![profile];
+        let mut current = self.content.profile(profile);
+
+        while let Some(p) = current {
+            let name = chain[chain.len() - 1];
+            let src = match p.get(SOURCE_PROFILE) {
+                Some(src) if src != name => src,
+                _ => break,
+            };
+
+            let is_cycle = chain.contains(&src);
+            chain.push(src);
+
+            if is_cycle {
+                return Err(chain);
+            }
+
+            current = self.content.profile(src);
+        }
+
+        Ok(chain)
     }
 
-    pub fn set_mfa_profile(self, src: &str, dst: &str) -> Result<Self> {
+    /// Copy the source profile to the destination with the keys the filter accepts.
+    /// `mfa_serial` is never copied, or the AWS CLI would ask for the MFA code again.
+    pub fn set_mfa_profile(
+        self,
+        src: &str,
+        dst: &str,
+        placement: Placement,
+        filter: &KeyFilter,
+    ) -> Result<Self> {
         let profile = self
             .content
             .profile(src)
             .cloned()
-            .ok_or(anyhow!(
-                "Not Found profile: {} at {}",
-                src,
-                filepath()?.to_string_lossy(),
-            ))?
+            .ok_or_else(|| self.profile_not_found(src))?
             .rename(dst)
-            .remove(MFA_SERIAL);
+            .retain_keys(|k| filter.is_copied(k))
+            .remove(MFA_SERIAL)
+            .mark_generated();
+
+        let content = match placement {
+            Placement::AfterSource => self.content.set_after(profile, src),
+            Placement::End => self.content.set(profile),
+        };
+
+        Ok(Self { content, ..self })
+    }
 
-        let content = self.content.set(profile);
+    pub fn set_value(self, profile: &str, key: &str, value: &str) -> Self {
+        let content = self.content.set_value(profile, key, value);
+        Self { content, ..self }
+    }
+
+    pub fn remove_value(self, profile: &str, key: &str) -> Self {
+        let content = self.content.remove_value(profile, key);
+        Self { content, ..self }
+    }
 
-        Ok(Self { content })
+    pub fn remove_profile(self, profile: &str) -> Self {
+        let content = self.content.remove(profile);
+        Self { content, ..self }
     }
 
     pub fn save(&self) -> Result<()> {
-        let path = filepath()?;
-        self.write(path.as_path())
+        self.write(&self.path)
+    }
+
+    pub fn diff(&self) -> Result<String> {
+        self.content.diff(&self.path, &SECRET_KEYS)
     }
 
-    fn load(path: &Path) -> Result<Self> {
+    fn profile_not_found(&self, profile: &str) -> anyhow::Error {
+        if self.path.exists() {
+            anyhow!(
+                "Not Found profile: {} at {}",
+                profile,
+                self.path.to_string_lossy(),
+            )
+        } else {
+            anyhow!(
+                "Not Found profile: {}. The config file {} does not exist. Run `awsmfa init` to create it.",
+                profile,
+                self.path.to_string_lossy(),
+            )
+        }
+    }
+
+    pub(super) fn load(path: &Path, strict: bool) -> Result<Self> {
         let fmt = Box::new(|p: &str| {
             if p == "default" {
                 "[default]".to_string()
             } else {
-                format!("[profile {p}]")
+                format!("[profile {}]", quote(p))
             }
         });
 
         let content = ConfLoader::new()
             .set_path(path)
-            .set_reg_profile(PROFILE)
+            .set_file_type(FileType::Config)
             .set_formatter(fmt)
+            .set_strict(strict)
             .load()?;
 
-        Ok(Self { content })
+        Ok(Self {
+            path: path.into(),
+            content,
+        })
     }
 
     fn write(&self, path: &Path) -> Result<()> {
@@ -81,17 +243,13 @@ impl Config {
     }
 }
 
-fn filepath() -> Result<PathBuf> {
-    Ok(aws_home()?.join(FILENAME))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn build() -> Config {
         let path = Path::new("mock/test_config");
-        Config::load(path).unwrap()
+        Config::load(path, false).unwrap()
     }
 
     #[test]
@@ -108,11 +266,93 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn it_resolves_profile_through_source_profiles() {
+        let config = build()
+            .set_value("role", "source_profile", "test")
+            .set_value("role", "role_arn", "arn:aws:iam::999999999999:role/admin")
+            .set_value("nested", "source_profile", "role")
+            .set_value(
+                "nested",
+                "mfa_serial",
+                "arn:aws:iam::999999999999:mfa/nested",
+            );
+
+        let resolved = config.resolve("test").unwrap();
+        assert_eq!(resolved.chain, vec!["test"]);
+        assert_eq!(resolved.mfa_serial, "arn:aws:iam::999999999999:mfa/user");
+
+        let resolved = config.resolve("role").unwrap();
+        assert_eq!(resolved.chain, vec!["role", "test"]);
+        assert_eq!(resolved.base(), "test");
+        assert_eq!(resolved.mfa_serial, "arn:aws:iam::999999999999:mfa/user");
+
+        // the nearest mfa_serial wins
+        let resolved = config.resolve("nested").unwrap();
+        assert_eq!(resolved.chain, vec!["nested", "role", "test"]);
+        assert_eq!(resolved.mfa_serial, "arn:aws:iam::999999999999:mfa/nested");
+
+        assert!(config.resolve("default").is_err());
+        assert!(config.resolve("unknown").is_err());
+    }
+
+    #[test]
+    fn it_resolves_source_profile_outside_config() {
+        let config = build()
+            .set_value("self", "source_profile", "self")
+            .set_value("self", "mfa_serial", "arn:aws:iam::999999999999:mfa/self")
+            .set_value("role", "source_profile", "creds_only")
+            .set_value("role", "mfa_serial", "arn:aws:iam::999999999999:mfa/role");
+
+        assert_eq!(config.resolve("self").unwrap().chain, vec!["self"]);
+        assert_eq!(
+            config.resolve("role").unwrap().chain,
+            vec!["role", "creds_only"]
+        );
+    }
+
+    #[test]
+    fn it_detects_source_profile_cycle() {
+        let config = build()
+            .set_value("a", "source_profile", "b")
+            .set_value("b", "source_profile", "c")
+            .set_value("c", "source_profile", "a")
+            .set_value("c", "mfa_serial", "arn:aws:iam::999999999999:mfa/user");
+
+        let err = config.resolve("a").unwrap_err().to_string();
+        assert!(err.contains("a -> b -> c -> a"));
+    }
+
+    #[test]
+    fn it_reads_duration_seconds() {
+        let config = build()
+            .set_value("test", "duration_seconds", "3600")
+            .set_value("long", "duration_seconds", "129600")
+            .set_value("short", "duration_seconds", "60")
+            .set_value("unit", "duration_seconds", "12h");
+
+        assert_eq!(config.duration("test").unwrap().unwrap().seconds(), 3600);
+        assert_eq!(config.duration("long").unwrap().unwrap().seconds(), 129600);
+        assert_eq!(config.duration("default").unwrap(), None);
+        assert_eq!(config.duration("unknown").unwrap(), None);
+
+        let err = config.duration("short").unwrap_err().to_string();
+        assert!(err.contains("Invalid duration_seconds in profile short"));
+
+        let err = config.duration("unit").unwrap_err().to_string();
+        assert!(err.contains("integer seconds"), "{err}");
+    }
+
     #[test]
     fn it_copies_profile_without_mfa_serial() {
         let config = build();
 
-        let result = config.set_mfa_profile("test", "test_v2");
+        let result = config.set_mfa_profile(
+            "test",
+            "test_v2",
+            Placement::AfterSource,
+            &KeyFilter::default(),
+        );
         assert!(result.is_ok());
 
         let config = result.unwrap();
@@ -127,6 +367,48 @@ mod tests {
 
         assert_eq!(test.get("region"), test_v2.get("region"));
         assert_eq!(test.get("output"), test_v2.get("output"));
+        assert_eq!(test_v2.get("s3.max_concurrent_requests"), Some("20"));
+    }
+
+    #[test]
+    fn it_copies_only_keys_the_filter_accepts() {
+        let config = build()
+            .set_value("test", "role_arn", "arn:aws:iam::999999999999:role/admin")
+            .set_value("test", "sso_start_url", "https://example.awsapps.com/start");
+
+        let result = config
+            .set_mfa_profile(
+                "test",
+                "test_v2",
+                Placement::AfterSource,
+                &KeyFilter::default(),
+            )
+            .unwrap();
+        let test_v2 = result.content.profile("test_v2").unwrap();
+        assert_eq!(test_v2.keys(), vec!["region", "output", "s3"]);
+
+        let filter = KeyFilter::new(Some(vec!["region".into()]), vec![]);
+        let result = result
+            .set_mfa_profile("test", "test_v2", Placement::AfterSource, &filter)
+            .unwrap();
+        let test_v2 = result.content.profile("test_v2").unwrap();
+        assert_eq!(test_v2.keys(), vec!["region"]);
+
+        // mfa_serial is removed even if the filter allows it
+        let filter = KeyFilter::new(None, vec![]);
+        let result = result
+            .set_mfa_profile("test", "test_v2", Placement::AfterSource, &filter)
+            .unwrap();
+        assert!(result.mfa_serial("test_v2").is_err());
+    }
+
+    #[test]
+    fn it_suggests_init_when_file_is_missing() {
+        let config = Config::load(Path::new("mock/notfound_config"), false).unwrap();
+
+        let err = config.mfa_serial("test").unwrap_err().to_string();
+        assert!(err.contains("mock/notfound_config"));
+        assert!(err.contains("awsmfa init"));
     }
 
     #[test]
@@ -137,7 +419,7 @@ mod tests {
         let result = config0.write(path);
         assert!(result.is_ok());
 
-        let config1 = Config::load(path).unwrap();
+        let config1 = Config::load(path, false).unwrap();
 
         assert_eq!(config0.content, config1.content);
     }
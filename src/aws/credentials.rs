@@ -1,23 +1,54 @@
+use crate::fs::insecure_mode;
 use crate::Result;
 
-use super::{aws_home, sts::StsCredential, ConfFile, ConfLoader, Profile};
+use super::{
+    filepath, sts::StsCredential, ConfFile, ConfLoader, FileType, Placement, Profile, SECRET_KEYS,
+};
+use chrono::prelude::*;
 use std::path::{Path, PathBuf};
 
-const PROFILE: &str = r"^\[(.+)\]$";
-const FILENAME: &str = "credentials";
+pub(super) const FILENAME: &str = "credentials";
+pub(super) const ENV_PATH: &str = "AWS_SHARED_CREDENTIALS_FILE";
+/// The key the expiration of the session credentials is written to in ISO 8601.
+pub(super) const EXPIRATION: &str = "aws_expiration";
 
 #[derive(Debug)]
 pub struct Credentials {
+    path: PathBuf,
     content: ConfFile,
 }
 
 impl Credentials {
-    pub fn new() -> Result<Self> {
-        let path = filepath()?;
-        Self::load(path.as_path())
+    pub fn new(strict: bool) -> Result<Self> {
+        let path = filepath(ENV_PATH, FILENAME)?;
+
+        if let Some(mode) = insecure_mode(&path)? {
+            eprintln!(
+                "Warning: Permissions {:04o} for \"{}\" are too open. Run with --fix-permissions to restrict them.",
+                mode,
+                path.display()
+            );
+        }
+
+        Self::load(path.as_path(), strict)
     }
 
-    pub fn set_cred(self, name: &str, cred: StsCredential) -> Self {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn content(&self) -> &ConfFile {
+        &self.content
+    }
+
+    pub fn set_cred(
+        self,
+        src: &str,
+        name: &str,
+        cred: StsCredential,
+        placement: Placement,
+    ) -> Self {
+        let expires_at = cred.expires_at();
         let StsCredential {
             access_key_id,
             secret_access_key,
@@ -25,30 +56,86 @@ impl Credentials {
             ..
         } = cred;
 
-        let profile = Profile::new(name)
+        // Overwrite the existing profile to keep its keys and their order.
+        let profile = self
+            .content
+            .profile(name)
+            .cloned()
+            .unwrap_or_else(|| Profile::new(name))
             .set("aws_access_key_id", &access_key_id)
             .set("aws_secret_access_key", &secret_access_key)
-            .set("aws_session_token", &session_token);
+            .set("aws_session_token", &session_token)
+            .mark_generated();
+
+        let profile = match expires_at {
+            Some(dt) => profile.set(EXPIRATION, &dt.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            None => profile.remove(EXPIRATION),
+        };
+
+        let content = match placement {
+            Placement::AfterSource => self.content.set_after(profile, src),
+            Placement::End => self.content.set(profile),
+        };
+
+        Self { content, ..self }
+    }
 
-        let content = self.content.set(profile);
+    pub fn set_value(self, profile: &str, key: &str, value: &str) -> Self {
+        let content = self.content.set_value(profile, key, value);
+        Self { content, ..self }
+    }
+
+    pub fn remove_value(self, profile: &str, key: &str) -> Self {
+        let content = self.content.remove_value(profile, key);
+        Self { content, ..self }
+    }
+
+    /// When the session credentials of the profile expire. `None` if the profile doesn't
+    /// record it or the value can't be parsed.
+    pub fn expiration(&self, profile: &str) -> Option<DateTime<Utc>> {
+        let value = self.content.profile(profile)?.get(EXPIRATION)?;
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+
+    pub fn remove_profile(self, profile: &str) -> Self {
+        let content = self.content.remove(profile);
+        Self { content, ..self }
+    }
 
-        Self { content }
+    /// Drop the secret keys of the profile, keeping the rest of the section.
+    pub fn remove_secrets(self, profile: &str) -> Self {
+        let content = match self.content.profile(profile).cloned() {
+            Some(p) => self
+                .content
+                .set(SECRET_KEYS.iter().fold(p, |p, key| p.remove(key))),
+            None => self.content,
+        };
+        Self { content, ..self }
     }
 
     pub fn save(&self) -> Result<()> {
-        let path = filepath()?;
-        self.write(path.as_path())
+        self.write(&self.path)
+    }
+
+    pub fn diff(&self) -> Result<String> {
+        self.content.diff(&self.path, &SECRET_KEYS)
     }
 
-    fn load(path: &Path) -> Result<Self> {
+    pub(super) fn load(path: &Path, strict: bool) -> Result<Self> {
         let fmt = Box::new(|p: &str| format!("[{p}]"));
         let content = ConfLoader::new()
             .set_path(path)
-            .set_reg_profile(PROFILE)
+            .set_file_type(FileType::Credentials)
             .set_formatter(fmt)
+            .set_strict(strict)
             .load()?;
 
-        Ok(Self { content })
+        Ok(Self {
+            path: path.into(),
+            content,
+        })
     }
 
     fn write(&self, path: &Path) -> Result<()> {
@@ -56,17 +143,22 @@ impl Credentials {
     }
 }
 
-fn filepath() -> Result<PathBuf> {
-    Ok(aws_home()?.join(FILENAME))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn build() -> Credentials {
         let path = Path::new("mock/test_credentials");
-        Credentials::load(path).unwrap()
+        Credentials::load(path, false).unwrap()
+    }
+
+    fn sts_credential() -> StsCredential {
+        StsCredential {
+            access_key_id: "access_key_id".to_string(),
+            secret_access_key: "secret_access_key".to_string(),
+            session_token: "session_token".to_string(),
+            ..Default::default()
+        }
     }
 
     #[test]
@@ -88,12 +180,9 @@ mod tests {
 
     #[test]
     fn it_sets_profile_from_sts_credentials() {
-        let mut cred = StsCredential::default();
-        cred.access_key_id = "access_key_id".to_string();
-        cred.secret_access_key = "secret_access_key".to_string();
-        cred.session_token = "session_token".to_string();
+        let cred = sts_credential();
 
-        let creds = build().set_cred("test", cred);
+        let creds = build().set_cred("tanaka", "test", cred, Placement::AfterSource);
         let result = creds.content.profile("test");
         assert!(result.is_some());
 
@@ -104,6 +193,51 @@ mod tests {
             Some("secret_access_key")
         );
         assert_eq!(profile.get("aws_session_token"), Some("session_token"));
+        assert_eq!(profile.get(EXPIRATION), None);
+    }
+
+    #[test]
+    fn it_records_expiration() {
+        let cred = StsCredential {
+            expiration: NaiveDateTime::from_timestamp_opt(1675155600, 0),
+            ..sts_credential()
+        };
+
+        let creds = build().set_cred("tanaka", "test", cred, Placement::AfterSource);
+        let profile = creds.content.profile("test").unwrap();
+        assert_eq!(profile.get(EXPIRATION), Some("2023-01-31T09:00:00Z"));
+        assert_eq!(
+            creds.expiration("test"),
+            Utc.with_ymd_and_hms(2023, 1, 31, 9, 0, 0).single()
+        );
+        assert_eq!(creds.expiration("tanaka"), None);
+    }
+
+    #[test]
+    fn it_overwrites_existing_profile_in_place() {
+        let cred = sts_credential();
+
+        let creds = build().set_cred("tanaka", "tanaka", cred, Placement::AfterSource);
+        let content = creds.content.format();
+        assert!(content.starts_with(
+            "[tanaka]\n# generated by awsmfa\naws_access_key_id=access_key_id\naws_secret_access_key=secret_access_key\naws_session_token = session_token\n\n[suzuki]"
+        ));
+    }
+
+    #[test]
+    fn it_redacts_secrets_in_diff() {
+        let cred = sts_credential();
+
+        let diff = build()
+            .set_cred("tanaka", "test", cred, Placement::AfterSource)
+            .diff()
+            .unwrap();
+
+        assert!(diff.contains("+[test]\n"));
+        assert!(diff.contains("+aws_access_key_id = access_key_id\n"));
+        assert!(diff.contains("+aws_secret_access_key = ********\n"));
+        assert!(diff.contains("+aws_session_token = ********\n"));
+        assert!(!diff.contains("session_token\n"));
     }
 
     #[test]
@@ -113,7 +247,7 @@ mod tests {
         let result = creds0.write(path);
         assert!(result.is_ok());
 
-        let creds1 = Credentials::load(path).unwrap();
+        let creds1 = Credentials::load(path, false).unwrap();
         assert_eq!(creds0.content, creds1.content);
     }
 }
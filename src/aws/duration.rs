@@ -0,0 +1,150 @@
+use crate::Result;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// How long the session credentials of AWS STS remain valid. The limits are of
+/// `GetSessionToken` for IAM users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionDuration(i32);
+
+impl SessionDuration {
+    pub const MIN: i32 = 900;
+    pub const MAX: i32 = 129600;
+
+    pub fn new(seconds: i64) -> Result<Self> {
+        if (Self::MIN as i64..=Self::MAX as i64).contains(&seconds) {
+            Ok(Self(seconds as i32))
+        } else {
+            Err(anyhow!(
+                "Duration {} seconds is out of the range AWS STS accepts: {} (15m) to {} (36h).",
+                seconds,
+                Self::MIN,
+                Self::MAX,
+            ))
+        }
+    }
+
+    pub fn seconds(&self) -> i32 {
+        self.0
+    }
+}
+
+impl FromStr for SessionDuration {
+    type Err = anyhow::Error;
+
+    /// Seconds such as `3600`, with a unit such as `90m`, `12h` or `1d`, or `max`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if s.eq_ignore_ascii_case("max") {
+            return Ok(Self(Self::MAX));
+        }
+
+        let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => s.split_at(i),
+            None => (s, "s"),
+        };
+
+        let multiplier = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => 0,
+        };
+
+        match number.parse::<i64>() {
+            Ok(n) if multiplier > 0 => Self::new(n.saturating_mul(multiplier)),
+            _ => Err(anyhow!(
+                "Invalid duration: \"{}\". Use seconds such as 3600, a unit such as 90m or 12h, or max.",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SessionDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for SessionDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SessionDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(i64),
+            Text(String),
+        }
+
+        let duration = match Raw::deserialize(deserializer)? {
+            Raw::Seconds(seconds) => Self::new(seconds),
+            Raw::Text(text) => text.parse(),
+        };
+
+        duration.map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<i32> {
+        s.parse::<SessionDuration>().ok().map(|d| d.seconds())
+    }
+
+    #[test]
+    fn it_parses_duration() {
+        assert_eq!(parse("3600"), Some(3600));
+        assert_eq!(parse("900s"), Some(900));
+        assert_eq!(parse("90m"), Some(5400));
+        assert_eq!(parse("12h"), Some(43200));
+        assert_eq!(parse("1d"), Some(86400));
+        assert_eq!(parse("max"), Some(129600));
+        assert_eq!(parse(" MAX "), Some(129600));
+    }
+
+    #[test]
+    fn it_rejects_invalid_duration() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("12x"), None);
+        assert_eq!(parse("h"), None);
+        assert_eq!(parse("-1"), None);
+        assert_eq!(parse("1.5h"), None);
+    }
+
+    #[test]
+    fn it_checks_sts_limits() {
+        assert_eq!(parse("899"), None);
+        assert_eq!(parse("14m"), None);
+        assert_eq!(parse("36h"), Some(129600));
+        assert_eq!(parse("37h"), None);
+        assert_eq!(parse("99999999999999999999"), None);
+
+        let err = "10m".parse::<SessionDuration>().unwrap_err().to_string();
+        assert!(err.contains("600 seconds"));
+    }
+
+    #[test]
+    fn it_reads_yaml() {
+        let d: SessionDuration = serde_yaml::from_str("3600").unwrap();
+        assert_eq!(d.seconds(), 3600);
+
+        let d: SessionDuration = serde_yaml::from_str("12h").unwrap();
+        assert_eq!(d.seconds(), 43200);
+
+        assert!(serde_yaml::from_str::<SessionDuration>("60").is_err());
+        assert_eq!(serde_yaml::to_string(&d).unwrap(), "43200\n");
+    }
+}
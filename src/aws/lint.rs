@@ -0,0 +1,389 @@
+use super::base::SectionKind;
+use super::config::Config;
+use super::{AwsConfigs, ConfFile, Profile};
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const MFA_SUFFIX: &str = "-mfa";
+
+/// The keys of a profile the AWS CLI and SDKs know about, and the ones awsmfa writes.
+const KNOWN_KEYS: &[&str] = &[
+    "account_id_endpoint_mode",
+    "api_versions",
+    "aws_access_key_id",
+    "aws_account_id",
+    "aws_expiration",
+    "aws_secret_access_key",
+    "aws_security_token",
+    "aws_session_token",
+    "ca_bundle",
+    "cli_auto_prompt",
+    "cli_binary_format",
+    "cli_follow_urlparam",
+    "cli_history",
+    "cli_pager",
+    "cli_timestamp_format",
+    "credential_process",
+    "credential_source",
+    "defaults_mode",
+    "disable_request_compression",
+    "duration_seconds",
+    "ec2_metadata_service_endpoint",
+    "ec2_metadata_service_endpoint_mode",
+    "ec2_metadata_v1_disabled",
+    "endpoint_discovery_enabled",
+    "endpoint_url",
+    "external_id",
+    "ignore_configured_endpoint_urls",
+    "max_attempts",
+    "metadata_service_num_attempts",
+    "metadata_service_timeout",
+    "mfa_serial",
+    "output",
+    "parameter_validation",
+    "region",
+    "request_checksum_calculation",
+    "request_min_compression_size_bytes",
+    "response_checksum_validation",
+    "retry_mode",
+    "role_arn",
+    "role_session_name",
+    "s3",
+    "services",
+    "sigv4a_signing_region_set",
+    "source_profile",
+    "sso_account_id",
+    "sso_region",
+    "sso_registration_scopes",
+    "sso_role_name",
+    "sso_session",
+    "sso_start_url",
+    "sts_regional_endpoints",
+    "tcp_keepalive",
+    "use_dualstack_endpoint",
+    "use_fips_endpoint",
+    "web_identity_token_file",
+];
+
+/// A problem found in the AWS files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    pub file: PathBuf,
+    pub section: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(file: &Path, section: &Profile, code: &'static str, message: String) -> Self {
+        Self {
+            file: file.into(),
+            section: section_name(section),
+            code,
+            message,
+        }
+    }
+}
+
+impl AwsConfigs {
+    /// Check the AWS files. `has_secret` tells whether awsmfa.yml has the secret key for
+    /// the profile.
+    pub fn lint<F: Fn(&str) -> bool>(&self, has_secret: F) -> Vec<Problem> {
+        let config = (self.config.path(), self.config.content());
+        let credentials = (self.credentials.path(), self.credentials.content());
+
+        let profiles = [config, credentials]
+            .iter()
+            .flat_map(|(_, content)| content.sections())
+            .filter(|p| p.kind().is_profile())
+            .map(|p| p.name())
+            .collect::<HashSet<&str>>();
+
+        let mut problems = vec![];
+
+        for (path, content) in [config, credentials] {
+            problems.extend(duplicates(path, content));
+            problems.extend(stale_profiles(path, content, &profiles));
+            problems.extend(unknown_keys(path, content));
+        }
+
+        problems.extend(source_profiles(&self.config, &profiles));
+        problems.extend(mfa_serials(&self.config, has_secret));
+
+        problems
+    }
+}
+
+fn duplicates(path: &Path, content: &ConfFile) -> Vec<Problem> {
+    content
+        .duplicates()
+        .into_iter()
+        .map(|p| {
+            Problem::new(
+                path,
+                p,
+                "duplicate-section",
+                format!("Section \"{}\" is defined more than once.", section_name(p)),
+            )
+        })
+        .collect()
+}
+
+/// A generated profile whose source profile was removed. The sections without the marker
+/// comment are the user's, even when their names end with `-mfa`.
+fn stale_profiles(path: &Path, content: &ConfFile, profiles: &HashSet<&str>) -> Vec<Problem> {
+    profiles_of(content)
+        .filter(|p| p.is_generated())
+        .filter_map(|p| {
+            let src = p.name().strip_suffix(MFA_SUFFIX)?;
+            if profiles.contains(src) {
+                None
+            } else {
+                Some(Problem::new(
+                    path,
+                    p,
+                    "stale-mfa-profile",
+                    format!(
+                        "Profile \"{}\" is generated from \"{src}\", which no longer exists.",
+                        p.name()
+                    ),
+                ))
+            }
+        })
+        .collect()
+}
+
+fn unknown_keys(path: &Path, content: &ConfFile) -> Vec<Problem> {
+    profiles_of(content)
+        .flat_map(|p| {
+            p.keys()
+                .into_iter()
+                .filter(|k| !KNOWN_KEYS.contains(&k.as_str()))
+                .map(|k| {
+                    Problem::new(
+                        path,
+                        p,
+                        "unknown-key",
+                        format!("Key \"{k}\" is not known to the AWS CLI."),
+                    )
+                })
+                .collect::<Vec<Problem>>()
+        })
+        .collect()
+}
+
+/// Follow the `source_profile` links the way `Config::resolve` does.
+fn source_profiles(config: &Config, profiles: &HashSet<&str>) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    for p in profiles_of(config.content()) {
+        if p.get("source_profile").is_none() {
+            continue;
+        }
+
+        match config.source_chain(p.name()) {
+            Ok(chain) => {
+                let base = chain[chain.len() - 1];
+                if !profiles.contains(base) {
+                    problems.push(Problem::new(
+                        config.path(),
+                        p,
+                        "missing-source-profile",
+                        format!("Source profile \"{base}\" does not exist."),
+                    ));
+                }
+            }
+            Err(cycle) => problems.push(Problem::new(
+                config.path(),
+                p,
+                "source-profile-cycle",
+                format!("Source profiles form a cycle: {}.", cycle.join(" -> ")),
+            )),
+        }
+    }
+
+    problems
+}
+
+/// A profile needs a secret when it resolves to an `mfa_serial`. The secret is looked up
+/// through the `source_profile` chain the way awsmfa does when it runs.
+fn mfa_serials<F: Fn(&str) -> bool>(config: &Config, has_secret: F) -> Vec<Problem> {
+    let path = config.path();
+    let mut problems = vec![];
+
+    for p in profiles_of(config.content()) {
+        if let Some(serial) = p.get("mfa_serial") {
+            if !is_valid_serial(serial) {
+                problems.push(Problem::new(
+                    path,
+                    p,
+                    "invalid-mfa-serial",
+                    format!("mfa_serial \"{serial}\" is neither an MFA device ARN nor a hardware serial number."),
+                ));
+            }
+        }
+
+        let resolved = match config.resolve(p.name()) {
+            Ok(resolved) => resolved,
+            Err(_) => continue,
+        };
+
+        if resolved.secret_profile(&has_secret).is_none() {
+            problems.push(Problem::new(
+                path,
+                p,
+                "missing-secret",
+                format!(
+                    "awsmfa.yml has no secret key for profile \"{}\" or its source profiles.",
+                    p.name()
+                ),
+            ));
+        }
+    }
+
+    problems
+}
+
+/// An MFA device ARN such as `arn:aws:iam::123456789012:mfa/name`, or the serial number
+/// of a hardware device.
+fn is_valid_serial(serial: &str) -> bool {
+    match serial.strip_prefix("arn:") {
+        Some(arn) => {
+            let parts = arn.splitn(5, ':').collect::<Vec<&str>>();
+            match parts.as_slice() {
+                [partition, "iam", "", account, resource] => {
+                    partition.starts_with("aws")
+                        && account.len() == 12
+                        && account.chars().all(|c| c.is_ascii_digit())
+                        && (resource.starts_with("mfa/") || resource.starts_with("u2f/"))
+                        && resource.len() > 4
+                }
+                _ => false,
+            }
+        }
+        None => {
+            (9..=256).contains(&serial.len()) && serial.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+    }
+}
+
+fn profiles_of(content: &ConfFile) -> impl Iterator<Item = &Profile> {
+    content.sections().iter().filter(|p| p.kind().is_profile())
+}
+
+fn section_name(p: &Profile) -> String {
+    match p.kind() {
+        SectionKind::Default | SectionKind::Profile => p.name().into(),
+        SectionKind::SsoSession => format!("sso-session {}", p.name()),
+        SectionKind::Services => format!("services {}", p.name()),
+        SectionKind::Unknown => p.name().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::credentials::Credentials;
+    use super::*;
+
+    fn lint(config: &str, credentials: &str, secrets: &[&str]) -> Vec<(&'static str, String)> {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config");
+        let credentials_path = dir.path().join("credentials");
+        std::fs::write(&config_path, config).unwrap();
+        std::fs::write(&credentials_path, credentials).unwrap();
+
+        let configs = AwsConfigs {
+            config: Config::load(&config_path, false).unwrap(),
+            credentials: Credentials::load(&credentials_path, false).unwrap(),
+        };
+        configs
+            .lint(|p| secrets.contains(&p))
+            .into_iter()
+            .map(|p| (p.code, p.section))
+            .collect()
+    }
+
+    #[test]
+    fn it_passes_valid_files() {
+        let problems = lint(
+            "[default]\nregion = us-east-1\nmfa_serial = arn:aws:iam::123456789012:mfa/user\n\n[profile role]\nrole_arn = arn:aws:iam::123456789012:role/admin\nsource_profile = default\n\n[profile default-mfa]\nregion = us-east-1\n",
+            "[default]\naws_access_key_id = A\naws_secret_access_key = B\n",
+            &["default"],
+        );
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn it_finds_problems() {
+        // corp-mfa isn't stale since awsmfa didn't generate it.
+        let problems = lint(
+            "[default]\nregion = us-east-1\n\n[profile default]\noutput = json\n\n[profile a]\nsource_profile = b\n\n[profile b]\nsource_profile = a\n\n[profile c]\nsource_profile = unknown\nmfa_serial = arn:aws:iam::123:mfa/user\n\n[profile d]\nmfa_serial = GAHT12345678\nfoo = bar\n",
+            "[old-mfa]\n# generated by awsmfa\naws_access_key_id = A\n\n[corp-mfa]\naws_access_key_id = B\n",
+            &["d"],
+        );
+
+        assert_eq!(
+            problems,
+            vec![
+                ("duplicate-section", "default".to_string()),
+                ("unknown-key", "d".to_string()),
+                ("stale-mfa-profile", "old-mfa".to_string()),
+                ("source-profile-cycle", "a".to_string()),
+                ("source-profile-cycle", "b".to_string()),
+                ("missing-source-profile", "c".to_string()),
+                ("invalid-mfa-serial", "c".to_string()),
+                ("missing-secret", "c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_allows_self_source_profile() {
+        let problems = lint(
+            "[profile a]\nrole_arn = arn:aws:iam::123456789012:role/admin\nsource_profile = a\n",
+            "[a]\naws_access_key_id = A\n",
+            &[],
+        );
+        assert!(problems.is_empty(), "{problems:?}");
+
+        let problems = lint(
+            "[profile a]\nrole_arn = arn:aws:iam::123456789012:role/admin\nsource_profile = a\n",
+            "",
+            &[],
+        );
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn it_looks_up_secrets_through_source_profiles() {
+        let config = "[profile base]\nmfa_serial = arn:aws:iam::123456789012:mfa/user\n\n[profile role]\nrole_arn = arn:aws:iam::123456789012:role/admin\nsource_profile = base\n";
+
+        let problems = lint(config, "[base]\naws_access_key_id = A\n", &["base"]);
+        assert!(problems.is_empty(), "{problems:?}");
+
+        let problems = lint(config, "[base]\naws_access_key_id = A\n", &[]);
+        assert_eq!(
+            problems,
+            vec![
+                ("missing-secret", "base".to_string()),
+                ("missing-secret", "role".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_validates_mfa_serial() {
+        assert!(is_valid_serial("arn:aws:iam::123456789012:mfa/user"));
+        assert!(is_valid_serial(
+            "arn:aws-us-gov:iam::123456789012:mfa/path/user"
+        ));
+        assert!(is_valid_serial("arn:aws:iam::123456789012:u2f/user/key"));
+        assert!(is_valid_serial("GAHT12345678"));
+        assert!(!is_valid_serial("arn:aws:iam::123:mfa/user"));
+        assert!(!is_valid_serial("arn:aws:iam::123456789012:user/name"));
+        assert!(!is_valid_serial("arn:aws:iam::123456789012:mfa/"));
+        assert!(!is_valid_serial("short"));
+    }
+}
@@ -1,17 +1,44 @@
-use crate::Result;
+use crate::backup::BackupFile;
+use crate::fs::create_private_dir;
+use crate::{Backup, FileLock, Result};
 
 mod base;
 mod config;
 mod credentials;
+mod duration;
+mod lint;
 mod sts;
 
 use anyhow::anyhow;
-use base::{ConfFile, ConfLoader, Profile};
+use base::{ConfFile, ConfLoader, FileType, Profile};
+pub use base::{KeyFilter, Placement};
+use chrono::{DateTime, Utc};
 use config::Config;
+pub use config::ResolvedProfile;
 use credentials::Credentials;
-use std::path::PathBuf;
+pub use duration::SessionDuration;
+pub use lint::Problem;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 pub use sts::GetSessionToken;
 
+const LOCK_FILENAME: &str = ".awsmfa.lock";
+
+/// The keys whose values are never printed.
+pub(crate) const SECRET_KEYS: [&str; 3] = [
+    "aws_secret_access_key",
+    "aws_session_token",
+    "aws_security_token",
+];
+
+/// The AWS files awsmfa edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwsFile {
+    Config,
+    Credentials,
+}
+
 #[derive(Debug)]
 pub struct AwsConfigs {
     config: Config,
@@ -19,37 +46,502 @@ pub struct AwsConfigs {
 }
 
 impl AwsConfigs {
-    pub fn new() -> Result<Self> {
+    /// Lock the AWS files against other awsmfa processes. Hold the lock from loading the
+    /// files until saving them.
+    pub fn lock(timeout: Duration) -> Result<FileLock> {
+        let credentials = credentials_filepath()?;
+        let dir = credentials
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        if !dir.exists() {
+            create_private_dir(dir)?;
+        }
+
+        FileLock::acquire(&dir.join(LOCK_FILENAME), timeout)
+    }
+
+    /// Load the AWS files. In strict mode, loading fails on the lines the parser doesn't
+    /// recognize.
+    pub fn new(strict: bool) -> Result<Self> {
         Ok(Self {
-            config: Config::new()?,
-            credentials: Credentials::new()?,
+            config: Config::new(strict)?,
+            credentials: Credentials::new(strict)?,
         })
     }
 
+    /// Resolve the profile reading only the config file, without the lock. The files are
+    /// replaced atomically, so this sees either version of them.
+    pub fn resolve_unlocked(name: &str, strict: bool) -> Result<ResolvedProfile> {
+        Config::new(strict)?.resolve(name)
+    }
+
     pub fn mfa_serial(&self, name: &str) -> Result<String> {
-        self.config.mfa_serial(name).map(String::from)
+        self.config.mfa_serial(name)
+    }
+
+    /// Resolve the profile through its `source_profile` chain to find the `mfa_serial`
+    /// and the base profile whose credentials call AWS STS.
+    pub fn resolve(&self, name: &str) -> Result<ResolvedProfile> {
+        self.config.resolve(name)
     }
 
-    pub fn set_cred(self, src: &str, dst: &str, cred: sts::StsCredential) -> Result<Self> {
+    /// Write the session credentials to the generated profile. A section of the same
+    /// name which awsmfa didn't generate is overwritten only with `force`.
+    pub fn set_cred(
+        self,
+        src: &str,
+        dst: &str,
+        cred: sts::StsCredential,
+        placement: Placement,
+        filter: &KeyFilter,
+        force: bool,
+    ) -> Result<Self> {
+        if !force {
+            self.ensure_generated(dst)?;
+        }
+
         let Self {
             config,
             credentials,
         } = self;
 
         Ok(Self {
-            config: config.set_mfa_profile(src, dst)?,
-            credentials: credentials.set_cred(dst, cred),
+            config: config.set_mfa_profile(src, dst, placement, filter)?,
+            credentials: credentials.set_cred(src, dst, cred, placement),
         })
     }
 
-    pub fn save(&self) -> Result<()> {
-        self.config.save()?;
-        self.credentials.save()
+    /// The session duration set by `duration_seconds` of the profile.
+    pub fn duration(&self, name: &str) -> Result<Option<SessionDuration>> {
+        self.config.duration(name)
+    }
+
+    /// When the session credentials of the generated profile expire, as recorded in the
+    /// credentials file.
+    pub fn expiration(&self, profile: &str) -> Option<DateTime<Utc>> {
+        self.credentials.expiration(profile)
+    }
+
+    /// The value of the key in the profile. A dotted key such as `s3.addressing_style`
+    /// gets the nested value.
+    pub fn get(&self, file: AwsFile, profile: &str, key: &str) -> Result<&str> {
+        let path = self.path(file);
+        let content = match file {
+            AwsFile::Config => self.config.content(),
+            AwsFile::Credentials => self.credentials.content(),
+        };
+
+        content
+            .profile(profile)
+            .ok_or(anyhow!(
+                "Not Found profile: {} at {}",
+                profile,
+                path.to_string_lossy(),
+            ))?
+            .get(key)
+            .ok_or(anyhow!(
+                "Not Found {} in profile {} at {}",
+                key,
+                profile,
+                path.to_string_lossy(),
+            ))
+    }
+
+    /// Set the value of the key in the profile. The profile is created if it doesn't
+    /// exist. Fails on the names and the value which would break the file.
+    pub fn set_value(self, file: AwsFile, profile: &str, key: &str, value: &str) -> Result<Self> {
+        check_entry(profile, key)?;
+        if value.contains(['\r', '\n']) {
+            return Err(anyhow!(
+                "Invalid value for key {}: it must not contain line breaks.",
+                key
+            ));
+        }
+
+        Ok(match file {
+            AwsFile::Config => Self {
+                config: self.config.set_value(profile, key, value),
+                ..self
+            },
+            AwsFile::Credentials => Self {
+                credentials: self.credentials.set_value(profile, key, value),
+                ..self
+            },
+        })
+    }
+
+    /// Remove the key from the profile. Removing a missing key does nothing.
+    pub fn remove_value(self, file: AwsFile, profile: &str, key: &str) -> Result<Self> {
+        check_entry(profile, key)?;
+
+        Ok(match file {
+            AwsFile::Config => Self {
+                config: self.config.remove_value(profile, key),
+                ..self
+            },
+            AwsFile::Credentials => Self {
+                credentials: self.credentials.remove_value(profile, key),
+                ..self
+            },
+        })
+    }
+
+    /// The names of the profiles awsmfa generated in either file, in the order of the
+    /// config file and then the credentials file.
+    pub fn generated_profiles(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+
+        for content in [self.config.content(), self.credentials.content()] {
+            for p in content.sections() {
+                if p.kind().is_profile() && p.is_generated() && !names.iter().any(|n| n == p.name())
+                {
+                    names.push(p.name().into());
+                }
+            }
+        }
+
+        names
+    }
+
+    /// Remove the profile from both files. A section awsmfa didn't generate is kept.
+    pub fn remove_generated(self, name: &str) -> Self {
+        let is_generated =
+            |content: &ConfFile| content.profile(name).is_some_and(|p| p.is_generated());
+
+        let config = if is_generated(self.config.content()) {
+            self.config.remove_profile(name)
+        } else {
+            self.config
+        };
+
+        let credentials = if is_generated(self.credentials.content()) {
+            self.credentials.remove_profile(name)
+        } else {
+            self.credentials
+        };
+
+        Self {
+            config,
+            credentials,
+        }
+    }
+
+    fn ensure_generated(&self, name: &str) -> Result<()> {
+        let files = [
+            (self.config.path(), self.config.content()),
+            (self.credentials.path(), self.credentials.content()),
+        ];
+
+        for (path, content) in files {
+            if content.profile(name).is_some_and(|p| !p.is_generated()) {
+                return Err(anyhow!(
+                    "Profile \"{}\" at {} was not generated by awsmfa. Run with --force to overwrite it.",
+                    name,
+                    path.to_string_lossy(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, backup: &Backup) -> Result<()> {
+        self.save_file(AwsFile::Config, backup)?;
+        self.save_file(AwsFile::Credentials, backup)
+    }
+
+    /// Save the files after removing the generated profiles. The secrets of the removed
+    /// profiles are dropped from the backups of the credentials file too, so that their
+    /// session tokens don't remain on disk.
+    pub fn save_purged(&self, backup: &Backup, names: &[String]) -> Result<()> {
+        self.save(backup)?;
+
+        for entry in backup
+            .list()?
+            .into_iter()
+            .filter(|e| e.file() == BackupFile::Credentials)
+        {
+            names
+                .iter()
+                .fold(Credentials::load(entry.path(), false)?, |c, name| {
+                    c.remove_secrets(name)
+                })
+                .save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Save only one of the files, leaving the other one and its backups untouched.
+    pub fn save_file(&self, file: AwsFile, backup: &Backup) -> Result<()> {
+        match file {
+            AwsFile::Config => {
+                backup.store(BackupFile::Config, self.config.path())?;
+                self.config.save()
+            }
+            AwsFile::Credentials => {
+                backup.store(BackupFile::Credentials, self.credentials.path())?;
+                self.credentials.save()
+            }
+        }
+    }
+
+    /// The unified diffs of the files that `save` would write.
+    pub fn diff(&self) -> Result<String> {
+        Ok(format!(
+            "{}{}",
+            self.config.diff()?,
+            self.credentials.diff()?
+        ))
+    }
+
+    pub fn path(&self, file: AwsFile) -> &Path {
+        match file {
+            AwsFile::Config => self.config.path(),
+            AwsFile::Credentials => self.credentials.path(),
+        }
+    }
+
+    pub fn config_path(&self) -> &Path {
+        self.config.path()
+    }
+
+    pub fn credentials_path(&self) -> &Path {
+        self.credentials.path()
     }
 }
 
-fn aws_home() -> Result<PathBuf> {
-    dirs::home_dir()
-        .ok_or(anyhow!("Failed to get home directory."))
-        .map(|p| p.join(".aws"))
+/// Check that the profile name and the key can be written to the files as they are.
+fn check_entry(profile: &str, key: &str) -> Result<()> {
+    if profile.trim().is_empty() || profile.contains([']', '\r', '\n']) {
+        return Err(anyhow!(
+            "Invalid profile name {:?}: it must not be empty or contain \"]\" or line breaks.",
+            profile
+        ));
+    }
+
+    let trimmed = key.trim();
+    if trimmed.is_empty() || key.contains(['=', '[', '\r', '\n']) || trimmed.starts_with(['#', ';'])
+    {
+        return Err(anyhow!(
+            "Invalid key {:?}: it must not be empty, contain \"=\", \"[\" or line breaks, or start with \"#\" or \";\".",
+            key
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn config_filepath() -> Result<PathBuf> {
+    filepath(config::ENV_PATH, config::FILENAME)
+}
+
+pub(crate) fn credentials_filepath() -> Result<PathBuf> {
+    filepath(credentials::ENV_PATH, credentials::FILENAME)
+}
+
+pub(crate) fn aws_home() -> Result<PathBuf> {
+    home_dir().map(|p| p.join(".aws"))
+}
+
+fn home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or(anyhow!("Failed to get home directory."))
+}
+
+/// Find the file the way the AWS CLI does. The environment variable takes precedence
+/// over the default file in `~/.aws`.
+fn filepath(env_name: &str, filename: &str) -> Result<PathBuf> {
+    resolve_path(env::var(env_name).ok(), filename)
+}
+
+fn resolve_path(env_value: Option<String>, filename: &str) -> Result<PathBuf> {
+    match env_value.filter(|v| !v.is_empty()) {
+        Some(value) => expand_tilde(&value),
+        None => Ok(aws_home()?.join(filename)),
+    }
+}
+
+fn expand_tilde(path: &str) -> Result<PathBuf> {
+    if path == "~" {
+        return home_dir();
+    }
+
+    match path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) {
+        Some(rest) => Ok(home_dir()?.join(rest)),
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build() -> AwsConfigs {
+        AwsConfigs {
+            config: Config::load(Path::new("mock/test_config"), false).unwrap(),
+            credentials: Credentials::load(Path::new("mock/test_credentials"), false).unwrap(),
+        }
+    }
+
+    fn set_cred(configs: AwsConfigs, dst: &str, force: bool) -> Result<AwsConfigs> {
+        configs.set_cred(
+            "test",
+            dst,
+            sts::StsCredential::default(),
+            Placement::AfterSource,
+            &KeyFilter::default(),
+            force,
+        )
+    }
+
+    #[test]
+    fn it_refuses_to_overwrite_hand_written_profile() {
+        let err = set_cred(build(), "test", false).unwrap_err().to_string();
+        assert!(err.contains("Profile \"test\" at mock/test_config"));
+        assert!(err.contains("--force"));
+
+        let configs = set_cred(build(), "tanaka", false);
+        assert!(configs.is_err());
+
+        assert!(set_cred(build(), "test", true).is_ok());
+    }
+
+    #[test]
+    fn it_overwrites_generated_profile() {
+        let configs = set_cred(build(), "test-mfa", false).unwrap();
+        assert!(set_cred(configs, "test-mfa", false).is_ok());
+    }
+
+    #[test]
+    fn it_removes_generated_profiles() {
+        let configs = set_cred(build(), "test-mfa", false).unwrap();
+        let configs = set_cred(configs, "other-mfa", false).unwrap();
+        assert_eq!(configs.generated_profiles(), vec!["other-mfa", "test-mfa"]);
+
+        let configs = configs
+            .remove_generated("test-mfa")
+            .remove_generated("test");
+        assert_eq!(configs.generated_profiles(), vec!["other-mfa"]);
+        assert!(configs.config.content().profile("test").is_some());
+        assert!(configs.credentials.content().profile("test-mfa").is_none());
+    }
+
+    #[test]
+    fn it_rejects_entries_breaking_the_file() {
+        let set = |profile: &str, key: &str, value: &str| {
+            build().set_value(AwsFile::Config, profile, key, value)
+        };
+
+        let configs = set("alpha", "region", "us-east-1").unwrap();
+        assert_eq!(
+            configs.get(AwsFile::Config, "alpha", "region").unwrap(),
+            "us-east-1"
+        );
+        assert!(set("alpha", "s3.addressing_style", "path").is_ok());
+
+        assert!(set("alpha", "region", "x\n[profile evil]\nrole_arn = y").is_err());
+        assert!(set("alpha", "region", "x\ry").is_err());
+
+        for key in [
+            "",
+            " ",
+            "a=b",
+            "[profile evil]",
+            "# comment",
+            "; comment",
+            "a\nb",
+            "a\rb",
+        ] {
+            assert!(set("alpha", key, "x").is_err(), "{key:?}");
+            assert!(
+                build().remove_value(AwsFile::Config, "alpha", key).is_err(),
+                "{key:?}"
+            );
+        }
+
+        for profile in ["", "a]b", "a\nb", "a\rb"] {
+            assert!(set(profile, "region", "x").is_err(), "{profile:?}");
+            assert!(
+                build()
+                    .remove_value(AwsFile::Credentials, profile, "region")
+                    .is_err(),
+                "{profile:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_keeps_no_removed_token_in_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config");
+        let credentials_path = dir.path().join("credentials");
+        std::fs::copy("mock/test_config", &config_path).unwrap();
+        std::fs::copy("mock/test_credentials", &credentials_path).unwrap();
+
+        let configs = AwsConfigs {
+            config: Config::load(&config_path, false).unwrap(),
+            credentials: Credentials::load(&credentials_path, false).unwrap(),
+        };
+        let cred = sts::StsCredential {
+            session_token: "purgedsessiontoken".into(),
+            ..Default::default()
+        };
+        let configs = configs
+            .set_cred(
+                "test",
+                "test-mfa",
+                cred,
+                Placement::End,
+                &KeyFilter::default(),
+                false,
+            )
+            .unwrap();
+
+        // Refreshing twice backs up the file holding the token.
+        let backup_dir = dir.path().join("backups");
+        let backup = Backup::with_dir(backup_dir.clone(), 10);
+        configs.save(&backup).unwrap();
+        configs.save(&backup).unwrap();
+
+        configs
+            .remove_generated("test-mfa")
+            .save_purged(&backup, &["test-mfa".into()])
+            .unwrap();
+
+        let credentials = std::fs::read_to_string(&credentials_path).unwrap();
+        assert!(!credentials.contains("purgedsessiontoken"));
+
+        let entries = backup
+            .list()
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.file() == BackupFile::Credentials)
+            .collect::<Vec<_>>();
+        assert!(!entries.is_empty());
+
+        for entry in entries {
+            let contents = std::fs::read_to_string(entry.path()).unwrap();
+            assert!(!contents.contains("purgedsessiontoken"));
+            // The credentials the user wrote are still backed up.
+            assert!(contents.contains("[tanaka]"), "{contents}");
+        }
+    }
+
+    #[test]
+    fn it_uses_aws_home_without_env_value() {
+        let expected = aws_home().unwrap().join("config");
+        assert_eq!(resolve_path(None, "config").unwrap(), expected);
+        assert_eq!(resolve_path(Some("".into()), "config").unwrap(), expected);
+    }
+
+    #[test]
+    fn it_uses_env_value() {
+        let path = resolve_path(Some("/etc/aws/config".into()), "config").unwrap();
+        assert_eq!(path, PathBuf::from("/etc/aws/config"));
+
+        let path = resolve_path(Some("~/ci/credentials".into()), "credentials").unwrap();
+        assert_eq!(path, home_dir().unwrap().join("ci/credentials"));
+    }
 }
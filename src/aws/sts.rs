@@ -70,7 +70,7 @@ pub struct StsCredential {
     pub access_key_id: String,
     pub secret_access_key: String,
     pub session_token: String,
-    expiration: Option<NaiveDateTime>,
+    pub(super) expiration: Option<NaiveDateTime>,
 }
 
 impl StsCredential {
@@ -79,6 +79,11 @@ impl StsCredential {
             .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
             .unwrap_or("Unknown".to_string())
     }
+
+    /// The expiration in UTC. STS returns it as a UTC timestamp.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expiration.map(|dt| Utc.from_utc_datetime(&dt))
+    }
 }
 
 impl TryFrom<GetSessionTokenOutput> for StsCredential {
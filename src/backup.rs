@@ -0,0 +1,314 @@
+use crate::aws;
+use crate::diff;
+use crate::fs::{create_private_dir, write_atomic};
+use crate::{MfaConfig, Result};
+
+use anyhow::anyhow;
+use chrono::prelude::*;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DIRNAME: &str = ".awsmfa-backups";
+const TIMESTAMP: &str = "%Y%m%dT%H%M%S%3f";
+
+/// The files awsmfa takes backups of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupFile {
+    Config,
+    Credentials,
+    MfaConfig,
+}
+
+impl BackupFile {
+    const ALL: [BackupFile; 3] = [Self::Config, Self::Credentials, Self::MfaConfig];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::Credentials => "credentials",
+            Self::MfaConfig => "awsmfa.yml",
+        }
+    }
+
+    /// The current location of the file.
+    pub fn path(&self) -> Result<PathBuf> {
+        match self {
+            Self::Config => aws::config_filepath(),
+            Self::Credentials => aws::credentials_filepath(),
+            Self::MfaConfig => MfaConfig::path(),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+}
+
+/// A backup of a file. Its id is made of the UTC timestamp and the file name, so ids sort
+/// by time even when the local clock goes back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupEntry {
+    id: String,
+    file: BackupFile,
+    path: PathBuf,
+}
+
+impl BackupEntry {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn file(&self) -> BackupFile {
+        self.file
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let id = path.file_name()?.to_str()?.to_string();
+        let (_, name) = id.split_once('-')?;
+        let file = BackupFile::from_name(name)?;
+
+        Some(Self { id, file, path })
+    }
+}
+
+impl fmt::Display for BackupEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}", self.id, self.file.name())
+    }
+}
+
+/// Keeps the copies of the files before awsmfa overwrites them.
+#[derive(Debug)]
+pub struct Backup {
+    dir: PathBuf,
+    retention: usize,
+}
+
+impl Backup {
+    /// Backups are kept in `~/.aws/.awsmfa-backups`. Only the newest `retention` backups
+    /// are kept for each file, and 0 disables backups.
+    pub fn new(retention: usize) -> Result<Self> {
+        Ok(Self::with_dir(aws::aws_home()?.join(DIRNAME), retention))
+    }
+
+    pub(crate) fn with_dir(dir: PathBuf, retention: usize) -> Self {
+        Self { dir, retention }
+    }
+
+    /// Copy the file at the path to the backup directory if it exists.
+    pub fn store(&self, file: BackupFile, path: &Path) -> Result<Option<BackupEntry>> {
+        if self.retention == 0 || !path.exists() {
+            return Ok(None);
+        }
+
+        if !self.dir.exists() {
+            create_private_dir(&self.dir)?;
+        }
+
+        let id = format!("{}-{}", Utc::now().format(TIMESTAMP), file.name());
+        let dst = self.dir.join(&id);
+        fs::copy(path, &dst).map_err(|err| {
+            anyhow!(
+                "Error backing up \"{}\" to \"{}\". {}",
+                path.display(),
+                dst.display(),
+                err
+            )
+        })?;
+
+        self.prune(file)?;
+
+        Ok(BackupEntry::from_path(dst))
+    }
+
+    /// All backups, newest first.
+    pub fn list(&self) -> Result<Vec<BackupEntry>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut entries = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| BackupEntry::from_path(e.path()))
+            .collect::<Vec<BackupEntry>>();
+
+        entries.sort_by(|a, b| b.id.cmp(&a.id));
+
+        Ok(entries)
+    }
+
+    pub fn find(&self, id: &str) -> Result<BackupEntry> {
+        self.list()?.into_iter().find(|e| e.id == id).ok_or(anyhow!(
+            "Not found backup: {}. Use backup ls command to see the backups.",
+            id
+        ))
+    }
+
+    /// Restore the file from the backup. The current file is backed up before it's
+    /// overwritten so that the restore itself can be rolled back.
+    pub fn restore(&self, entry: &BackupEntry, path: &Path) -> Result<()> {
+        let contents = fs::read(&entry.path)?;
+        self.store(entry.file, path)?;
+        write_atomic(path, &contents)
+    }
+
+    /// Remove all the backups of the file. Return the number of the removed backups.
+    pub fn remove_all(&self, file: BackupFile) -> Result<usize> {
+        let entries = self
+            .list()?
+            .into_iter()
+            .filter(|e| e.file == file)
+            .collect::<Vec<BackupEntry>>();
+
+        for entry in entries.iter() {
+            fs::remove_file(&entry.path)?;
+        }
+
+        Ok(entries.len())
+    }
+
+    /// The unified diff that `restore` would make to the file, with the secrets redacted.
+    pub fn diff(&self, entry: &BackupEntry, path: &Path) -> Result<String> {
+        let contents = fs::read_to_string(&entry.path)?;
+        diff::unified(path, &contents, |line| match entry.file {
+            BackupFile::MfaConfig => diff::redact_yaml(line, &["value"]),
+            _ => diff::redact_ini(line, &aws::SECRET_KEYS),
+        })
+    }
+
+    fn prune(&self, file: BackupFile) -> Result<()> {
+        let stale = self
+            .list()?
+            .into_iter()
+            .filter(|e| e.file == file)
+            .skip(self.retention);
+
+        for entry in stale {
+            fs::remove_file(&entry.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(retention: usize) -> (tempfile::TempDir, Backup, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = Backup::with_dir(dir.path().join(DIRNAME), retention);
+        let path = dir.path().join("config");
+        (dir, backup, path)
+    }
+
+    #[test]
+    fn it_stores_backup() {
+        let (_dir, backup, path) = setup(10);
+
+        // nothing to back up
+        assert_eq!(backup.store(BackupFile::Config, &path).unwrap(), None);
+
+        fs::write(&path, "[default]\n").unwrap();
+        let entry = backup.store(BackupFile::Config, &path).unwrap().unwrap();
+        assert!(entry.id().ends_with("-config"));
+        assert_eq!(entry.file(), BackupFile::Config);
+        assert_eq!(fs::read_to_string(&entry.path).unwrap(), "[default]\n");
+
+        assert_eq!(backup.list().unwrap(), vec![entry]);
+    }
+
+    #[test]
+    fn it_keeps_only_retention_count() {
+        let (_dir, backup, path) = setup(2);
+        fs::write(&path, "[default]\n").unwrap();
+
+        for _ in 0..4 {
+            backup.store(BackupFile::Config, &path).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        backup.store(BackupFile::Credentials, &path).unwrap();
+
+        let entries = backup.list().unwrap();
+        let configs = entries
+            .iter()
+            .filter(|e| e.file() == BackupFile::Config)
+            .count();
+        assert_eq!(configs, 2);
+        assert_eq!(entries.len(), 3);
+    }
+
+    #[test]
+    fn it_disables_backup_with_zero_retention() {
+        let (_dir, backup, path) = setup(0);
+        fs::write(&path, "[default]\n").unwrap();
+
+        assert_eq!(backup.store(BackupFile::Config, &path).unwrap(), None);
+        assert!(backup.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_removes_all_backups_of_file() {
+        let (_dir, backup, path) = setup(10);
+        fs::write(&path, "[default]\n").unwrap();
+
+        backup.store(BackupFile::MfaConfig, &path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        backup.store(BackupFile::MfaConfig, &path).unwrap();
+        backup.store(BackupFile::Config, &path).unwrap();
+
+        assert_eq!(backup.remove_all(BackupFile::MfaConfig).unwrap(), 2);
+        let entries = backup.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file(), BackupFile::Config);
+    }
+
+    #[test]
+    fn it_restores_from_backup() {
+        let (_dir, backup, path) = setup(10);
+        fs::write(&path, "[default]\nregion = us-east-1\n").unwrap();
+        let entry = backup.store(BackupFile::Config, &path).unwrap().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        fs::write(&path, "broken").unwrap();
+        let found = backup.find(entry.id()).unwrap();
+        backup.restore(&found, &path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[default]\nregion = us-east-1\n"
+        );
+
+        // the overwritten contents are backed up too
+        let entries = backup.list().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(fs::read_to_string(&entries[0].path).unwrap(), "broken");
+
+        assert!(backup.find("unknown-config").is_err());
+    }
+
+    #[test]
+    fn it_diffs_restore_without_writing() {
+        let (_dir, backup, path) = setup(10);
+        let old = "[default]\nregion = us-east-1\naws_session_token = oldtoken\n";
+        let new = "[default]\nregion = eu-west-1\naws_session_token = newtoken\n";
+        fs::write(&path, old).unwrap();
+        let entry = backup
+            .store(BackupFile::Credentials, &path)
+            .unwrap()
+            .unwrap();
+
+        fs::write(&path, new).unwrap();
+        let diff = backup.diff(&entry, &path).unwrap();
+        assert!(diff.contains("-region = eu-west-1\n"));
+        assert!(diff.contains("+region = us-east-1\n"));
+        assert!(!diff.contains("token\n"));
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), new);
+        assert_eq!(backup.list().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{MfaConfig, Result};
+
+use clap::Subcommand;
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List backups, newest first.
+    Ls,
+}
+
+pub fn run(config: MfaConfig, args: &Args) -> Result<()> {
+    match args.command {
+        Commands::Ls => {
+            let entries = config.backup()?.list()?;
+
+            if entries.is_empty() {
+                println!("There are no backups.");
+            }
+
+            for entry in entries {
+                println!("{entry}");
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::aws::AwsConfigs;
+use crate::{MfaConfig, Result};
+
+use chrono::Utc;
+use std::time::Duration;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Remove only the profile generated from this profile.
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    /// Remove only the profiles whose session credentials have expired.
+    #[arg(long)]
+    expired_only: bool,
+}
+
+pub fn run(
+    config: MfaConfig,
+    args: &Args,
+    strict: bool,
+    dry_run: bool,
+    lock_timeout: Duration,
+) -> Result<()> {
+    let Args {
+        profile,
+        expired_only,
+    } = args;
+
+    let _lock = AwsConfigs::lock(lock_timeout)?;
+    let aws_configs = AwsConfigs::new(strict)?;
+    let target = profile.as_ref().map(|p| format!("{p}-mfa"));
+    let now = Utc::now();
+
+    // A profile without the expiration can't be told to have expired, so it's kept.
+    let names = aws_configs
+        .generated_profiles()
+        .into_iter()
+        .filter(|name| target.as_ref().is_none_or(|t| t == name))
+        .filter(|name| !expired_only || aws_configs.expiration(name).is_some_and(|e| e <= now))
+        .collect::<Vec<String>>();
+
+    if names.is_empty() {
+        println!("There are no profiles to remove.");
+        return Ok(());
+    }
+
+    let aws_configs = names
+        .iter()
+        .fold(aws_configs, |configs, name| configs.remove_generated(name));
+
+    if dry_run {
+        print!("{}", aws_configs.diff()?);
+        return Ok(());
+    }
+
+    aws_configs.save_purged(&config.backup()?, &names)?;
+
+    for name in names {
+        println!("Removed profile \"{name}\".");
+    }
+    println!("Updated {}", aws_configs.config_path().display());
+    println!("Updated {}", aws_configs.credentials_path().display());
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::aws::{AwsConfigs, AwsFile};
+use crate::{MfaConfig, Result};
+
+use clap::{Subcommand, ValueEnum};
+use std::time::Duration;
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Print the value of the key.
+    Get(Target),
+
+    /// Set the value of the key. The profile is created if it doesn't exist.
+    Set(SetArgs),
+
+    /// Remove the key.
+    Unset(Target),
+}
+
+#[derive(clap::Args)]
+#[group(skip)]
+struct Target {
+    /// Profile name
+    #[arg(short, long)]
+    profile: String,
+
+    /// Key name. Use a dotted key such as s3.addressing_style for a nested value.
+    #[arg(short, long)]
+    key: String,
+
+    /// The file to operate on.
+    #[arg(short, long, value_enum, default_value_t = File::Config)]
+    file: File,
+}
+
+#[derive(clap::Args)]
+struct SetArgs {
+    #[command(flatten)]
+    target: Target,
+
+    /// Value to set
+    #[arg(short, long)]
+    value: String,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum File {
+    Config,
+    Credentials,
+}
+
+impl From<File> for AwsFile {
+    fn from(file: File) -> Self {
+        match file {
+            File::Config => AwsFile::Config,
+            File::Credentials => AwsFile::Credentials,
+        }
+    }
+}
+
+pub fn run(
+    config: MfaConfig,
+    args: &Args,
+    strict: bool,
+    dry_run: bool,
+    lock_timeout: Duration,
+) -> Result<()> {
+    match &args.command {
+        Commands::Get(Target { profile, key, file }) => {
+            let aws_configs = AwsConfigs::new(strict)?;
+            println!("{}", aws_configs.get((*file).into(), profile, key)?);
+            Ok(())
+        }
+        Commands::Set(SetArgs {
+            target: Target { profile, key, file },
+            value,
+        }) => {
+            let _lock = AwsConfigs::lock(lock_timeout)?;
+            let file = AwsFile::from(*file);
+            let aws_configs = AwsConfigs::new(strict)?.set_value(file, profile, key, value)?;
+            save(&config, &aws_configs, file, dry_run)
+        }
+        Commands::Unset(Target { profile, key, file }) => {
+            let _lock = AwsConfigs::lock(lock_timeout)?;
+            let file = AwsFile::from(*file);
+            let aws_configs = AwsConfigs::new(strict)?.remove_value(file, profile, key)?;
+            save(&config, &aws_configs, file, dry_run)
+        }
+    }
+}
+
+fn save(config: &MfaConfig, aws_configs: &AwsConfigs, file: AwsFile, dry_run: bool) -> Result<()> {
+    if dry_run {
+        print!("{}", aws_configs.diff()?);
+        return Ok(());
+    }
+
+    aws_configs.save_file(file, &config.backup()?)?;
+    println!("Updated {}", aws_configs.path(file).display());
+    Ok(())
+}
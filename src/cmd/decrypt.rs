@@ -0,0 +1,15 @@
+use crate::crypto::passphrase;
+use crate::{MfaConfig, Result};
+
+pub fn run(config: MfaConfig, dry_run: bool) -> Result<()> {
+    let config = config.decrypt(&passphrase(false)?)?;
+
+    if dry_run {
+        print!("{}", config.diff()?);
+        return Ok(());
+    }
+
+    config.save()?;
+    println!("Decrypted the secrets in awsmfa.yml.");
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use crate::crypto::passphrase;
+use crate::{MfaConfig, Result};
+
+pub fn run(config: MfaConfig, dry_run: bool) -> Result<()> {
+    let config = config.encrypt(&passphrase(true)?)?;
+
+    if dry_run {
+        print!("{}", config.diff()?);
+        return Ok(());
+    }
+
+    // The backups of awsmfa.yml hold the plaintext secrets.
+    let removed = config.save_discarding_backups()?;
+
+    println!("Encrypted the secrets in awsmfa.yml.");
+    if removed > 0 {
+        println!("Removed {removed} backup(s) of awsmfa.yml holding the plaintext secrets.");
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::aws::{config_filepath, credentials_filepath};
+use crate::fs::write_atomic;
+use crate::{MfaConfig, Result};
+
+/// With `dry_run`, print the files to create instead of creating them.
+pub fn run(dry_run: bool) -> Result<()> {
+    for path in [config_filepath()?, credentials_filepath()?] {
+        if path.exists() {
+            println!("\"{}\" already exists.", path.display());
+        } else if dry_run {
+            println!("Would create \"{}\".", path.display());
+        } else {
+            write_atomic(&path, b"")?;
+            println!("Created \"{}\".", path.display());
+        }
+    }
+
+    let path = MfaConfig::path()?;
+    if path.exists() {
+        println!("\"{}\" already exists.", path.display());
+    } else if dry_run {
+        println!("Would create \"{}\".", path.display());
+        print!("{}", MfaConfig::default().diff()?);
+    } else {
+        MfaConfig::default().save()?;
+        println!("Created \"{}\".", path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use crate::aws::AwsConfigs;
+use crate::{MfaConfig, Result};
+
+use anyhow::anyhow;
+
+/// Print the problems as JSON lines. Fails when there is any problem so that the exit
+/// code can be checked in scripts.
+pub fn run(config: MfaConfig, strict: bool) -> Result<()> {
+    let problems = AwsConfigs::new(strict)?.lint(|p| config.has_secret(p));
+
+    for problem in &problems {
+        println!("{}", serde_json::to_string(problem)?);
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        n => Err(anyhow!("Found {} problem(s).", n)),
+    }
+}
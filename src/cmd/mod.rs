@@ -1,15 +1,29 @@
 use clap::Subcommand;
 
+pub mod backup;
+pub mod clean;
+pub mod config;
+pub mod decrypt;
+pub mod encrypt;
+pub mod init;
+pub mod lint;
 pub mod ls;
 pub mod otp;
+pub mod restore;
 pub mod rm;
 pub mod set;
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// Create the AWS config, credentials and awsmfa.yml files if they don't exist.
+    Init,
+
     /// List mfa devices.
     Ls,
 
+    /// Check the AWS files for problems and print them as JSON lines.
+    Lint,
+
     /// Set mfa device to config file.
     Set(set::Args),
 
@@ -18,4 +32,22 @@ pub enum Commands {
 
     /// Remove mfa device from config file.
     Rm(rm::Args),
+
+    /// Operate backups of the AWS files and awsmfa.yml.
+    Backup(backup::Args),
+
+    /// Restore the file from the backup.
+    Restore(restore::Args),
+
+    /// Remove the profiles generated by awsmfa from the AWS files.
+    Clean(clean::Args),
+
+    /// Encrypt the secrets in awsmfa.yml with a passphrase.
+    Encrypt,
+
+    /// Decrypt the secrets in awsmfa.yml back to plaintext.
+    Decrypt,
+
+    /// Get, set or unset any key of a profile in the AWS files.
+    Config(config::Args),
 }
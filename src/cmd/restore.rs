@@ -0,0 +1,33 @@
+use crate::aws::AwsConfigs;
+use crate::backup::BackupFile;
+use crate::{MfaConfig, Result};
+
+use std::time::Duration;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Backup id shown by backup ls command
+    id: String,
+}
+
+pub fn run(config: MfaConfig, args: &Args, dry_run: bool, lock_timeout: Duration) -> Result<()> {
+    let Args { id } = args;
+    let backup = config.backup()?;
+    let entry = backup.find(id)?;
+
+    let _lock = match entry.file() {
+        BackupFile::MfaConfig => None,
+        _ => Some(AwsConfigs::lock(lock_timeout)?),
+    };
+
+    let path = entry.file().path()?;
+
+    if dry_run {
+        print!("{}", backup.diff(&entry, &path)?);
+        return Ok(());
+    }
+
+    backup.restore(&entry, &path)?;
+    println!("Restored \"{}\" from backup {id}.", path.display());
+    Ok(())
+}
@@ -7,9 +7,16 @@ pub struct Args {
     profile: String,
 }
 
-pub fn run(config: MfaConfig, args: &Args) -> Result<()> {
+pub fn run(config: MfaConfig, args: &Args, dry_run: bool) -> Result<()> {
     let Args { profile } = args;
-    config.remove(profile).save()?;
+    let config = config.remove(profile);
+
+    if dry_run {
+        print!("{}", config.diff()?);
+        return Ok(());
+    }
+
+    config.save()?;
     println!("Remove the secret key for profile \"{profile}\" successfully.");
     Ok(())
 }
@@ -11,9 +11,17 @@ pub struct Args {
     secret: String,
 }
 
-pub fn run(config: MfaConfig, args: &Args) -> Result<()> {
+pub fn run(config: MfaConfig, args: &Args, dry_run: bool) -> Result<()> {
     let Args { profile, secret } = args;
-    config.set(profile, secret).save()?;
+    let secret = config.encrypt_secret(secret)?;
+    let config = config.set(profile, &secret);
+
+    if dry_run {
+        print!("{}", config.diff()?);
+        return Ok(());
+    }
+
+    config.save()?;
     println!("Saved the secret key for profile \"{profile}\" successfully.");
     Ok(())
 }
@@ -1,14 +1,48 @@
+use crate::aws::{KeyFilter, Placement, SessionDuration};
+use crate::backup::{Backup, BackupFile};
+use crate::crypto::{self, Cipher, Encryption};
+use crate::diff;
+use crate::fs::{insecure_mode, write_atomic};
 use crate::Result;
 
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct MfaConfig {
     secrets: Vec<Secret>,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    placement: Placement,
+
+    #[serde(default = "default_backup_retention")]
+    #[serde(skip_serializing_if = "is_default_backup_retention")]
+    backup_retention: usize,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    key_filter: KeyFilter,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<SessionDuration>,
+
+    /// The secret values are encrypted when this is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<Encryption>,
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        Self {
+            secrets: vec![],
+            placement: Placement::default(),
+            backup_retention: default_backup_retention(),
+            key_filter: KeyFilter::default(),
+            duration: None,
+            encryption: None,
+        }
+    }
 }
 
 impl fmt::Display for MfaConfig {
@@ -18,6 +52,12 @@ impl fmt::Display for MfaConfig {
                 f,
                 "There are no secret keys in ~/.aws/awsmfa.yml. Use set command to register your first secret key."
             )
+        } else if self.is_encrypted() {
+            for s in self.secrets.iter() {
+                writeln!(f, "[profile {}]", s.profile)?;
+                writeln!(f, "secret\t: (encrypted)\n")?;
+            }
+            write!(f, "")
         } else {
             for s in self.secrets.iter() {
                 writeln!(f, "{s}")?;
@@ -31,6 +71,10 @@ impl fmt::Display for MfaConfig {
 struct Secret {
     profile: String,
     value: String,
+
+    /// Overrides the global key filter for the profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_filter: Option<KeyFilter>,
 }
 
 impl fmt::Display for Secret {
@@ -43,18 +87,37 @@ impl fmt::Display for Secret {
 impl MfaConfig {
     pub fn new() -> Result<Self> {
         let path = Self::path()?;
+
+        // Like ssh does for private keys, refuse the secrets others can read.
+        if let Some(mode) = insecure_mode(&path)? {
+            return Err(anyhow!(
+                "Permissions {:04o} for \"{}\" are too open. It holds the secret keys of your MFA devices and must not be accessible by others. Run with --fix-permissions to restrict them.",
+                mode,
+                path.display()
+            ));
+        }
+
         Self::load(path.as_path())
     }
 
     pub fn set(self, profile: &str, value: &str) -> Self {
-        let mut secrets = self.remove(profile).secrets;
+        // Keep the key filter of the profile when the secret is replaced.
+        let key_filter = self
+            .secrets
+            .iter()
+            .find(|s| s.profile == profile)
+            .and_then(|s| s.key_filter.clone());
+
+        let config = self.remove(profile);
+        let mut secrets = config.secrets;
 
         secrets.push(Secret {
             profile: profile.into(),
             value: value.into(),
+            key_filter,
         });
 
-        Self { secrets }
+        Self { secrets, ..config }
     }
 
     pub fn remove(self, profile: &str) -> Self {
@@ -64,16 +127,61 @@ impl MfaConfig {
             .filter(|s| s.profile != profile)
             .collect();
 
-        Self { secrets }
+        Self { secrets, ..self }
+    }
+
+    /// Where the generated "-mfa" profiles are put in the AWS files.
+    pub fn placement(&self) -> Placement {
+        self.placement
+    }
+
+    /// Which keys are copied to the generated "-mfa" profile. The filter of the profile
+    /// takes precedence over the global one.
+    pub fn key_filter(&self, profile: &str) -> &KeyFilter {
+        self.secrets
+            .iter()
+            .find(|s| s.profile == profile)
+            .and_then(|s| s.key_filter.as_ref())
+            .unwrap_or(&self.key_filter)
+    }
+
+    /// The session duration used when neither the duration option nor `duration_seconds`
+    /// of the profile is given.
+    pub fn duration(&self) -> Option<SessionDuration> {
+        self.duration
     }
 
     pub fn save(&self) -> Result<()> {
         let path = Self::path()?;
+        self.backup()?.store(BackupFile::MfaConfig, &path)?;
         self.write(path.as_path())
     }
 
+    /// Save the file without taking a backup, and remove the backups taken before. Use
+    /// this when the backups would keep the plaintext secrets on disk. Return the number
+    /// of the removed backups.
+    pub fn save_discarding_backups(&self) -> Result<usize> {
+        self.write(Self::path()?.as_path())?;
+        self.backup()?.remove_all(BackupFile::MfaConfig)
+    }
+
+    /// The unified diff of the file that `save` would write, with the secrets redacted.
+    pub fn diff(&self) -> Result<String> {
+        let content = serde_yaml::to_string(self).map_err(anyhow::Error::new)?;
+        diff::unified(&Self::path()?, &content, |line| {
+            diff::redact_yaml(line, &["value"])
+        })
+    }
+
+    /// The backup of the files keeping as many backups as configured.
+    pub fn backup(&self) -> Result<Backup> {
+        Backup::new(self.backup_retention)
+    }
+
+    /// The secret of the profile. The passphrase is asked if the secrets are encrypted.
     pub fn get_secret(&self, profile: &str) -> Result<String> {
-        self.secrets
+        let value = self
+            .secrets
             .iter()
             .find_map(|s| {
                 if s.profile == profile {
@@ -82,7 +190,82 @@ impl MfaConfig {
                     None
                 }
             })
-            .ok_or(anyhow!("Not found mfa device for profile: {}", profile))
+            .ok_or(anyhow!("Not found mfa device for profile: {}", profile))?;
+
+        match &self.encryption {
+            Some(encryption) => encryption
+                .cipher(&crypto::passphrase(false)?)?
+                .decrypt(&value),
+            None => Ok(value),
+        }
+    }
+
+    /// The value to store for the secret, which is encrypted if the other secrets are.
+    pub fn encrypt_secret(&self, value: &str) -> Result<String> {
+        match &self.encryption {
+            Some(encryption) => encryption
+                .cipher(&crypto::passphrase(false)?)?
+                .encrypt(value),
+            None => Ok(value.into()),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Encrypt all the secrets with the key derived from the passphrase.
+    pub fn encrypt(self, passphrase: &str) -> Result<Self> {
+        if self.is_encrypted() {
+            return Err(anyhow!("The secrets in awsmfa.yml are already encrypted."));
+        }
+
+        let (encryption, cipher) = Encryption::new(passphrase)?;
+        self.encrypt_with(encryption, &cipher)
+    }
+
+    /// Decrypt all the secrets back to plaintext.
+    pub fn decrypt(self, passphrase: &str) -> Result<Self> {
+        let cipher = match &self.encryption {
+            Some(encryption) => encryption.cipher(passphrase)?,
+            None => return Err(anyhow!("The secrets in awsmfa.yml are not encrypted.")),
+        };
+
+        let secrets = self
+            .secrets
+            .into_iter()
+            .map(|s| {
+                let value = cipher.decrypt(&s.value)?;
+                Ok(Secret { value, ..s })
+            })
+            .collect::<Result<Vec<Secret>>>()?;
+
+        Ok(Self {
+            secrets,
+            encryption: None,
+            ..self
+        })
+    }
+
+    fn encrypt_with(self, encryption: Encryption, cipher: &Cipher) -> Result<Self> {
+        let secrets = self
+            .secrets
+            .into_iter()
+            .map(|s| {
+                let value = cipher.encrypt(&s.value)?;
+                Ok(Secret { value, ..s })
+            })
+            .collect::<Result<Vec<Secret>>>()?;
+
+        Ok(Self {
+            secrets,
+            encryption: Some(encryption),
+            ..self
+        })
+    }
+
+    pub fn has_secret(&self, profile: &str) -> bool {
+        self.secrets.iter().any(|s| s.profile == profile)
     }
 
     fn load(path: &Path) -> Result<Self> {
@@ -96,17 +279,29 @@ impl MfaConfig {
     }
 
     fn write(&self, path: &Path) -> Result<()> {
-        let file = fs::File::create(path).map_err(anyhow::Error::new)?;
-        serde_yaml::to_writer(file, self).map_err(anyhow::Error::new)
+        let content = serde_yaml::to_string(self).map_err(anyhow::Error::new)?;
+        write_atomic(path, content.as_bytes())
     }
 
-    fn path() -> Result<PathBuf> {
+    pub(crate) fn path() -> Result<PathBuf> {
         dirs::home_dir()
             .ok_or(anyhow!("Failed to get home directory."))
             .map(|p| p.join(".aws/awsmfa.yml"))
     }
 }
 
+fn default_backup_retention() -> usize {
+    10
+}
+
+fn is_default_backup_retention(value: &usize) -> bool {
+    *value == default_backup_retention()
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,6 +356,85 @@ mod tests {
         assert_eq!(secret.value, "new_secret");
     }
 
+    #[test]
+    fn it_reads_placement() {
+        let config: MfaConfig = serde_yaml::from_str("secrets: []\nplacement: end\n").unwrap();
+        assert_eq!(config.placement(), Placement::End);
+
+        // defaults to put the profile after its source
+        let config = MfaConfig::load(Path::new("mock/test.yml")).unwrap();
+        assert_eq!(config.placement(), Placement::AfterSource);
+    }
+
+    #[test]
+    fn it_reads_backup_retention() {
+        let config: MfaConfig = serde_yaml::from_str("secrets: []\nbackup_retention: 3\n").unwrap();
+        assert_eq!(config.backup_retention, 3);
+
+        let config = MfaConfig::load(Path::new("mock/test.yml")).unwrap();
+        assert_eq!(config.backup_retention, 10);
+    }
+
+    #[test]
+    fn it_reads_key_filter() {
+        let yaml = "secrets:\n  - profile: prod\n    value: secret\n    key_filter:\n      allow:\n        - region\nkey_filter:\n  deny:\n    - output\n";
+        let config: MfaConfig = serde_yaml::from_str(yaml).unwrap();
+
+        let filter = config.key_filter("prod");
+        assert!(filter.is_copied("region"));
+        assert!(!filter.is_copied("cli_pager"));
+        assert!(!filter.is_copied("role_arn"));
+
+        let filter = config.key_filter("dev");
+        assert!(!filter.is_copied("output"));
+        assert!(filter.is_copied("role_arn"));
+
+        // the filter of the profile survives replacing the secret
+        let config = config.set("prod", "new_secret");
+        assert!(!config.key_filter("prod").is_copied("cli_pager"));
+
+        let config = MfaConfig::load(Path::new("mock/test.yml")).unwrap();
+        assert_eq!(config.key_filter("test"), &KeyFilter::default());
+    }
+
+    #[test]
+    fn it_reads_duration() {
+        let config: MfaConfig = serde_yaml::from_str("secrets: []\nduration: 12h\n").unwrap();
+        assert_eq!(config.duration().map(|d| d.seconds()), Some(43200));
+
+        let result = serde_yaml::from_str::<MfaConfig>("secrets: []\nduration: 1m\n");
+        assert!(result.is_err());
+
+        let config = MfaConfig::load(Path::new("mock/test.yml")).unwrap();
+        assert_eq!(config.duration(), None);
+    }
+
+    #[test]
+    fn it_encrypts_and_decrypts_secrets() {
+        let config = MfaConfig::load(Path::new("mock/test.yml")).unwrap();
+        let (encryption, cipher) = crypto::tests::encryption("passphrase");
+        let config = config.encrypt_with(encryption, &cipher).unwrap();
+
+        assert!(config.is_encrypted());
+        assert_ne!(config.secrets[0].value, "somesecret");
+        assert!(config.to_string().contains("(encrypted)"));
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(!yaml.contains("somesecret"));
+        assert!(config.encrypt("passphrase").is_err());
+
+        let config: MfaConfig = serde_yaml::from_str(&yaml).unwrap();
+
+        let config = config.decrypt("wrong");
+        assert!(config.is_err());
+
+        let config: MfaConfig = serde_yaml::from_str(&yaml).unwrap();
+        let config = config.decrypt("passphrase").unwrap();
+        assert!(!config.is_encrypted());
+        assert_eq!(config.get_secret("test").unwrap(), "somesecret");
+        assert!(config.decrypt("passphrase").is_err());
+    }
+
     #[test]
     fn it_writes_contents() {
         let path = Path::new("mock/write_test.yml");
@@ -0,0 +1,212 @@
+use crate::Result;
+
+use anyhow::anyhow;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// The environment variable which provides the passphrase without a terminal.
+pub const ENV_PASSPHRASE: &str = "AWSMFA_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+/// The plaintext encrypted as `check`, which tells a wrong passphrase before anything
+/// is encrypted with it.
+const CHECK: &str = "awsmfa";
+
+/// How the secrets are encrypted. The key is derived from the passphrase by Argon2id and
+/// each secret is encrypted by XChaCha20-Poly1305. The parameters are kept so that
+/// changing the defaults doesn't break the files encrypted before.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Encryption {
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    check: String,
+}
+
+impl Encryption {
+    /// Generate a new salt and derive the key from the passphrase.
+    pub fn new(passphrase: &str) -> Result<(Self, Cipher)> {
+        Self::with_params(
+            passphrase,
+            Params::DEFAULT_M_COST,
+            Params::DEFAULT_T_COST,
+            Params::DEFAULT_P_COST,
+        )
+    }
+
+    fn with_params(
+        passphrase: &str,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    ) -> Result<(Self, Cipher)> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        let mut encryption = Self {
+            salt: STANDARD.encode(salt),
+            m_cost,
+            t_cost,
+            p_cost,
+            check: String::new(),
+        };
+
+        let cipher = encryption.derive(passphrase)?;
+        encryption.check = cipher.encrypt(CHECK)?;
+
+        Ok((encryption, cipher))
+    }
+
+    /// Derive the key from the passphrase. Fails if the passphrase is wrong.
+    pub fn cipher(&self, passphrase: &str) -> Result<Cipher> {
+        let cipher = self.derive(passphrase)?;
+
+        match cipher.decrypt(&self.check) {
+            Ok(check) if check == CHECK => Ok(cipher),
+            _ => Err(anyhow!("Wrong passphrase for the secrets in awsmfa.yml.")),
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<Cipher> {
+        let salt = decode(&self.salt)?;
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_LEN))
+            .map_err(|e| anyhow!("Invalid encryption parameters in awsmfa.yml. {}", e))?;
+
+        let mut key = [0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow!("Failed to derive the key from the passphrase. {}", e))?;
+
+        Ok(Cipher(XChaCha20Poly1305::new(Key::from_slice(&key))))
+    }
+}
+
+/// Encrypts and decrypts the secrets with the key derived from the passphrase.
+pub struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    /// Encrypt the plaintext to the base64 of the random nonce followed by the ciphertext.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt the secret."))?;
+
+        Ok(STANDARD.encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String> {
+        let bytes = decode(encrypted)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(anyhow!("The encrypted secret in awsmfa.yml is too short."));
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .0
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                anyhow!("Failed to decrypt the secret in awsmfa.yml. It may be corrupted.")
+            })?;
+
+        String::from_utf8(plaintext).map_err(anyhow::Error::new)
+    }
+}
+
+/// Read the passphrase from the environment variable, or prompt for it on the terminal.
+/// With `confirm`, the prompt asks for it twice.
+pub fn passphrase(confirm: bool) -> Result<String> {
+    if let Some(value) = env::var(ENV_PASSPHRASE).ok().filter(|v| !v.is_empty()) {
+        return Ok(value);
+    }
+
+    let prompt = |message: &str| {
+        rpassword::prompt_password(message).map_err(|e| {
+            anyhow!(
+                "Failed to read the passphrase: {}. Set {} to provide it without a terminal.",
+                e,
+                ENV_PASSPHRASE
+            )
+        })
+    };
+
+    let value = prompt("Passphrase for awsmfa.yml: ")?;
+
+    if value.is_empty() {
+        return Err(anyhow!("The passphrase must not be empty."));
+    }
+
+    if confirm && prompt("Confirm passphrase: ")? != value {
+        return Err(anyhow!("The passphrases don't match."));
+    }
+
+    Ok(value)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| anyhow!("Invalid base64 in awsmfa.yml. {}", e))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Cheap parameters to keep the tests fast.
+    pub(crate) fn encryption(passphrase: &str) -> (Encryption, Cipher) {
+        Encryption::with_params(passphrase, 8, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn it_encrypts_and_decrypts() {
+        let (_, cipher) = encryption("passphrase");
+        let encrypted = cipher.encrypt("somesecret").unwrap();
+        assert_ne!(encrypted, "somesecret");
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "somesecret");
+
+        // a random nonce makes each ciphertext different
+        assert_ne!(cipher.encrypt("somesecret").unwrap(), encrypted);
+    }
+
+    #[test]
+    fn it_derives_same_key_from_passphrase() {
+        let (encryption, cipher) = encryption("passphrase");
+        let encrypted = cipher.encrypt("somesecret").unwrap();
+
+        let yaml = serde_yaml::to_string(&encryption).unwrap();
+        let encryption: Encryption = serde_yaml::from_str(&yaml).unwrap();
+
+        let cipher = encryption.cipher("passphrase").unwrap();
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "somesecret");
+    }
+
+    #[test]
+    fn it_rejects_wrong_passphrase() {
+        let (encryption, _) = encryption("passphrase");
+        let err = encryption.cipher("wrong").err().unwrap().to_string();
+        assert!(err.contains("Wrong passphrase"));
+    }
+
+    #[test]
+    fn it_rejects_tampered_secret() {
+        let (_, cipher) = encryption("passphrase");
+        let mut bytes = decode(&cipher.encrypt("somesecret").unwrap()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(cipher.decrypt(&STANDARD.encode(bytes)).is_err());
+        assert!(cipher.decrypt("c2hvcnQ=").is_err());
+        assert!(cipher.decrypt("not base64!").is_err());
+    }
+}
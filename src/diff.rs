@@ -0,0 +1,105 @@
+use crate::Result;
+
+use anyhow::anyhow;
+use similar::TextDiff;
+use std::fs;
+use std::path::Path;
+
+const REDACTED: &str = "********";
+
+/// The unified diff between the file and the contents about to be written to it. Each
+/// line of both sides is passed through `redact` so that secrets are not printed.
+pub fn unified<F: Fn(&str) -> String>(path: &Path, contents: &str, redact: F) -> Result<String> {
+    let current = if path.exists() {
+        fs::read_to_string(path)
+            .map_err(|err| anyhow!("Error reading \"{}\". {}", path.display(), err))?
+    } else {
+        String::new()
+    };
+
+    let redact_all = |text: &str| {
+        text.split_inclusive('\n')
+            .map(|line| match line.strip_suffix('\n') {
+                Some(line) => format!("{}\n", redact(line)),
+                None => redact(line),
+            })
+            .collect::<String>()
+    };
+    let (old, new) = (redact_all(&current), redact_all(contents));
+
+    let name = path.display().to_string();
+    Ok(TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .header(&name, &name)
+        .to_string())
+}
+
+/// Replace the value of the `key = value` line if the key is one of the keys.
+pub fn redact_ini(line: &str, keys: &[&str]) -> String {
+    match line.split_once('=') {
+        Some((k, _)) if keys.contains(&k.trim()) => format!("{k}= {REDACTED}"),
+        _ => line.into(),
+    }
+}
+
+/// Replace the value of the `key: value` line if the key is one of the keys.
+pub fn redact_yaml(line: &str, keys: &[&str]) -> String {
+    match line.split_once(':') {
+        Some((k, _)) if keys.contains(&k.trim().trim_start_matches("- ")) => {
+            format!("{k}: {REDACTED}")
+        }
+        _ => line.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_shows_unified_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials");
+        fs::write(&path, "[default]\naws_access_key_id = OLD\n").unwrap();
+
+        let diff = unified(&path, "[default]\naws_access_key_id = NEW\n", |l| l.into()).unwrap();
+        let name = path.display();
+        assert_eq!(
+            diff,
+            format!(
+                "--- {name}\n+++ {name}\n@@ -1,2 +1,2 @@\n [default]\n-aws_access_key_id = OLD\n+aws_access_key_id = NEW\n"
+            )
+        );
+
+        // no changes
+        let diff = unified(&path, "[default]\naws_access_key_id = OLD\n", |l| l.into()).unwrap();
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn it_shows_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+
+        let diff = unified(&path, "[default]\n", |l| l.into()).unwrap();
+        assert!(diff.ends_with("@@ -0,0 +1 @@\n+[default]\n"));
+    }
+
+    #[test]
+    fn it_redacts_secrets() {
+        let keys = ["aws_session_token"];
+        assert_eq!(
+            redact_ini("aws_session_token = abc", &keys),
+            "aws_session_token = ********"
+        );
+        assert_eq!(
+            redact_ini("aws_access_key_id = abc", &keys),
+            "aws_access_key_id = abc"
+        );
+
+        let keys = ["value"];
+        assert_eq!(redact_yaml("  value: abc", &keys), "  value: ********");
+        assert_eq!(redact_yaml("- value: abc", &keys), "- value: ********");
+        assert_eq!(redact_yaml("- profile: abc", &keys), "- profile: abc");
+    }
+}
@@ -0,0 +1,289 @@
+use crate::Result;
+
+use anyhow::anyhow;
+use fs2::FileExt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An advisory exclusive lock on a file. The lock is released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    file: fs::File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Acquire the lock, waiting for other processes to release it until the timeout.
+    pub fn acquire(path: &Path, timeout: Duration) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|err| anyhow!("Failed to open lock file \"{}\". {}", path.display(), err))?;
+        let started = Instant::now();
+
+        while file.try_lock_exclusive().is_err() {
+            if started.elapsed() >= timeout {
+                return Err(anyhow!(
+                    "Timed out after {} seconds waiting for the lock on \"{}\". Another awsmfa process may be updating the AWS files.",
+                    timeout.as_secs(),
+                    path.display()
+                ));
+            }
+
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+
+        Ok(Self {
+            file,
+            path: path.into(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Write the contents to the file atomically. The contents are written to a temporary
+/// file in the same directory first and then renamed over the target, so the target is
+/// never left half-written. The permissions of the original file are kept.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    write(path, contents).map_err(|err| {
+        anyhow!(
+            "Error writing to \"{}\". {}",
+            path.to_str().unwrap_or("unknown path"),
+            err
+        )
+    })
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    // Write through a symbolic link such as the one managed in a dotfiles repository.
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    if !dir.exists() {
+        create_private_dir(dir)?;
+    }
+
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+
+    match fs::metadata(&path) {
+        Ok(metadata) => file.as_file().set_permissions(metadata.permissions())?,
+        Err(_) => set_private(file.as_file())?,
+    }
+
+    file.as_file().sync_all()?;
+    file.persist(&path)?;
+    sync_dir(dir)
+}
+
+/// Create the directory and its parents accessible only by the owner.
+#[cfg(unix)]
+pub fn create_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn create_private_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+/// The permission bits of the file if the group or others can access it.
+#[cfg(unix)]
+pub fn insecure_mode(path: &Path) -> Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+    Ok(Some(mode).filter(|m| m & 0o077 != 0))
+}
+
+#[cfg(not(unix))]
+pub fn insecure_mode(_path: &Path) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Make the file readable and writable only by the owner. Return true if the
+/// permissions were changed.
+pub fn restrict_permissions(path: &Path) -> Result<bool> {
+    if insecure_mode(path)?.is_none() {
+        return Ok(false);
+    }
+
+    set_private(&fs::File::open(path)?)?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn set_private(file: &fs::File) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_private(_file: &fs::File) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+
+        write_atomic(&path, b"[default]\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[default]\n");
+
+        write_atomic(&path, b"[profile test]\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[profile test]\n");
+
+        // no temporary files are left
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn it_waits_for_lock_until_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".awsmfa.lock");
+
+        let lock = FileLock::acquire(&path, Duration::ZERO).unwrap();
+        assert_eq!(lock.path(), path);
+
+        let result = FileLock::acquire(&path, Duration::from_millis(200));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Timed out"));
+
+        drop(lock);
+        assert!(FileLock::acquire(&path, Duration::ZERO).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_creates_missing_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".aws/credentials");
+
+        write_atomic(&path, b"[default]\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[default]\n");
+
+        let mode = fs::metadata(dir.path().join(".aws"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_atomic(&path, b"[default]\n").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_creates_private_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("awsmfa.yml");
+
+        write_atomic(&path, b"secrets: []\n").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(insecure_mode(&path).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_restricts_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("awsmfa.yml");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        assert_eq!(insecure_mode(&path).unwrap(), Some(0o644));
+        assert!(restrict_permissions(&path).unwrap());
+        assert_eq!(insecure_mode(&path).unwrap(), None);
+
+        // nothing to do any more
+        assert!(!restrict_permissions(&path).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_writes_through_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles_config");
+        let link = dir.path().join("config");
+        fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"[default]\n").unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "[default]\n");
+    }
+}
@@ -1,12 +1,19 @@
 use anyhow::{anyhow, Error};
+use std::path::PathBuf;
 use totp_rs::{Algorithm, Secret, TOTP};
 
 pub mod aws;
+mod backup;
 pub mod cmd;
 mod config;
+mod crypto;
+mod diff;
+mod fs;
 
 pub type Result<T> = std::result::Result<T, Error>;
+pub use backup::Backup;
 pub use config::MfaConfig;
+pub use fs::FileLock;
 
 pub fn get_otp(config: &MfaConfig, profile: &str) -> Result<String> {
     let secret = Secret::Encoded(config.get_secret(profile)?.to_ascii_uppercase())
@@ -16,3 +23,23 @@ pub fn get_otp(config: &MfaConfig, profile: &str) -> Result<String> {
         .map_err(|e| anyhow!("{:#?}", e))
         .and_then(|totp| totp.generate_current().map_err(Error::new))
 }
+
+/// Restrict the permissions of the files holding secrets to the owner. Return the files
+/// whose permissions were changed, or only find them with `dry_run`.
+pub fn fix_permissions(dry_run: bool) -> Result<Vec<PathBuf>> {
+    let mut fixed = vec![];
+
+    for path in [MfaConfig::path()?, aws::credentials_filepath()?] {
+        let restricted = if dry_run {
+            fs::insecure_mode(&path)?.is_some()
+        } else {
+            fs::restrict_permissions(&path)?
+        };
+
+        if restricted {
+            fixed.push(path);
+        }
+    }
+
+    Ok(fixed)
+}
@@ -1,6 +1,7 @@
-use awsmfa::aws::{AwsConfigs, GetSessionToken};
-use awsmfa::{cmd, get_otp, MfaConfig, Result};
+use awsmfa::aws::{AwsConfigs, GetSessionToken, SessionDuration};
+use awsmfa::{cmd, fix_permissions, get_otp, MfaConfig, Result};
 use clap::Parser;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "awsmfa")]
@@ -10,9 +11,29 @@ struct Cli {
     #[arg(short, long)]
     profile: Option<String>,
 
-    /// Duration seconds that the credentials should remain valid.
+    /// Duration that the credentials should remain valid, such as 3600, 90m, 12h or max.
     #[arg(short, long)]
-    duration: Option<i32>,
+    duration: Option<SessionDuration>,
+
+    /// Seconds to wait for another awsmfa process to finish updating the AWS files.
+    #[arg(long, default_value_t = 30)]
+    lock_timeout: u64,
+
+    /// Fail on the lines of the AWS files which can't be parsed instead of keeping them as they are.
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the changes to the files instead of writing them.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Overwrite the profile to generate even if awsmfa didn't generate it.
+    #[arg(long)]
+    force: bool,
+
+    /// Restrict the permissions of awsmfa.yml and the AWS credentials file to the owner.
+    #[arg(long)]
+    fix_permissions: bool,
 
     /// Commands to read or write config file.
     #[command(subcommand)]
@@ -30,40 +51,126 @@ async fn main() {
 async fn run() -> Result<()> {
     let cli = Cli::parse();
 
+    if cli.fix_permissions {
+        for path in fix_permissions(cli.dry_run)? {
+            if cli.dry_run {
+                println!(
+                    "Would restrict the permissions of \"{}\" to 0600.",
+                    path.display()
+                );
+            } else {
+                println!(
+                    "Restricted the permissions of \"{}\" to 0600.",
+                    path.display()
+                );
+            }
+        }
+    }
+
     let config = MfaConfig::new()?;
 
     match &cli.command {
+        Some(cmd::Commands::Init) => cmd::init::run(cli.dry_run),
         Some(cmd::Commands::Ls) => cmd::ls::run(config),
-        Some(cmd::Commands::Set(args)) => cmd::set::run(config, args),
+        Some(cmd::Commands::Lint) => cmd::lint::run(config, cli.strict),
+        Some(cmd::Commands::Set(args)) => cmd::set::run(config, args, cli.dry_run),
         Some(cmd::Commands::Otp(args)) => cmd::otp::run(config, args),
-        Some(cmd::Commands::Rm(args)) => cmd::rm::run(config, args),
+        Some(cmd::Commands::Rm(args)) => cmd::rm::run(config, args, cli.dry_run),
+        Some(cmd::Commands::Backup(args)) => cmd::backup::run(config, args),
+        Some(cmd::Commands::Restore(args)) => cmd::restore::run(
+            config,
+            args,
+            cli.dry_run,
+            Duration::from_secs(cli.lock_timeout),
+        ),
+        Some(cmd::Commands::Clean(args)) => cmd::clean::run(
+            config,
+            args,
+            cli.strict,
+            cli.dry_run,
+            Duration::from_secs(cli.lock_timeout),
+        ),
+        Some(cmd::Commands::Encrypt) => cmd::encrypt::run(config, cli.dry_run),
+        Some(cmd::Commands::Decrypt) => cmd::decrypt::run(config, cli.dry_run),
+        Some(cmd::Commands::Config(args)) => cmd::config::run(
+            config,
+            args,
+            cli.strict,
+            cli.dry_run,
+            Duration::from_secs(cli.lock_timeout),
+        ),
         None => {
             let opt_profile = cli.profile;
             let opt_duration = cli.duration;
             let profile = &opt_profile.clone().unwrap_or("default".to_string());
 
-            let mfa_profile = &format!("{profile}-mfa");
-            let aws_configs = AwsConfigs::new()?;
+            // Get the code before taking the lock, since asking for the passphrase can take
+            // a while and the other awsmfa processes would wait for it.
+            let resolved = AwsConfigs::resolve_unlocked(profile, cli.strict)?;
+
+            // The secret is registered for the profile or one of its source profiles.
+            let secret_profile = resolved
+                .secret_profile(|p| config.has_secret(p))
+                .unwrap_or(profile);
+            let token_code = get_otp(&config, secret_profile)?;
+
+            let _lock = AwsConfigs::lock(Duration::from_secs(cli.lock_timeout))?;
+            let aws_configs = AwsConfigs::new(cli.strict)?;
+            let resolved = aws_configs.resolve(profile)?;
+
+            // The session credentials are those of the base profile, without the role of
+            // the profile, so the generated profile is named after the base one.
+            let base = resolved.base().to_string();
+            let mfa_profile = &format!("{base}-mfa");
 
-            let serial_number = aws_configs.mfa_serial(profile)?;
-            let token_code = get_otp(&config, profile)?;
+            // The option takes precedence over the profile, and the profile over awsmfa.yml.
+            let duration = match opt_duration {
+                Some(duration) => Some(duration),
+                None => aws_configs.duration(profile)?.or(config.duration()),
+            };
+
+            // Without the profile option, let the SDK pick the profile from the environment
+            // as long as the default profile doesn't inherit its credentials.
+            let sts_profile = match opt_profile {
+                None if base == *profile => None,
+                _ => Some(base.clone()),
+            };
 
             let sts_cred = GetSessionToken::new()
-                .set_profile(opt_profile)
-                .set_duration_seconds(opt_duration)
-                .set_serial_number(Some(serial_number))
+                .set_profile(sts_profile)
+                .set_duration_seconds(duration.map(|d| d.seconds()))
+                .set_serial_number(Some(resolved.mfa_serial))
                 .set_token_code(Some(token_code))
                 .send()
                 .await?;
 
             let expiration = sts_cred.expiration();
 
-            aws_configs
-                .set_cred(profile, mfa_profile, sts_cred)
-                .and_then(|conf| conf.save())?;
+            let aws_configs = aws_configs.set_cred(
+                &base,
+                mfa_profile,
+                sts_cred,
+                config.placement(),
+                config.key_filter(&base),
+                cli.force,
+            )?;
+
+            if cli.dry_run {
+                print!("{}", aws_configs.diff()?);
+                return Ok(());
+            }
+
+            aws_configs.save(&config.backup()?)?;
 
             println!("New credentials is available as profile \"{mfa_profile}\".");
+            if base != *profile {
+                println!(
+                    "It carries the identity of profile \"{base}\", not the role of \"{profile}\". To assume the role with it, set source_profile = {mfa_profile} in a role profile."
+                );
+            }
             println!("It is valid until {expiration}.");
+            println!("Updated {}", aws_configs.config_path().display());
+            println!("Updated {}", aws_configs.credentials_path().display());
 
             Ok(())
         }